@@ -0,0 +1,73 @@
+//! Measures how long a fetch takes for a subscriber that has acked everything, while a second
+//! subscriber that never acks keeps the messages of the topic around.
+//!
+//! Start the broker with `SPIPES_STORAGE=memory`, then run
+//! `cargo run --release --example fetch_latency`.
+
+// Also holds the broker's own snapshot and journal messages, unused here.
+#[allow(dead_code)]
+#[path = "../src/broker_service.rs"]
+mod broker_service;
+
+use broker_service::broker_service_client::BrokerServiceClient;
+use broker_service::{AckBatchRequest, AckRequest, CreateTopicRequest, FetchRequest, PostBatchRequest, PostRequest, SubscribeRequest};
+use std::time::{Duration, Instant};
+use tonic::transport::Channel;
+use uuid::Uuid;
+
+const SERVER_URL: &str = "http://127.0.0.1:5005";
+const TOPIC_SIZES: [usize; 3] = [1_000, 10_000, 50_000];
+const FETCHES: u32 = 200;
+const CHUNK: usize = 1_000;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = BrokerServiceClient::connect(SERVER_URL).await?;
+    println!("{:>12}  {:>12}", "messages", "fetch");
+    for size in TOPIC_SIZES {
+        let latency = measure(&mut client, size).await?;
+        println!("{:>12}  {:>12?}", size, latency);
+    }
+    Ok(())
+}
+
+/// Fills a new topic with `size` messages, has one subscriber ack them all and returns the
+/// average duration of its fetches afterwards.
+async fn measure(client: &mut BrokerServiceClient<Channel>, size: usize) -> Result<Duration, Box<dyn std::error::Error>> {
+    let run = Uuid::new_v4();
+    let topic_name = format!("bench-{}", run);
+    let reader = format!("bench-reader-{}", run);
+    client.create_topic(CreateTopicRequest { name: topic_name.clone(), ..Default::default() }).await?;
+    for client_id in [reader.clone(), format!("bench-idle-{}", run)] {
+        client.subscribe(SubscribeRequest { topic_name: topic_name.clone(), client_id, ..Default::default() }).await?;
+    }
+
+    for first in (0..size).step_by(CHUNK) {
+        let posts = (first..size.min(first + CHUNK))
+            .map(|i| PostRequest { topic_name: topic_name.clone(), payload: format!("msg-{}", i), ..Default::default() })
+            .collect();
+        client.post_batch(PostBatchRequest { posts }).await?;
+    }
+    let mut acked = 0;
+    while acked < size {
+        let fetched = fetch(client, &reader, CHUNK as u32).await?;
+        let acks = fetched
+            .into_iter()
+            .map(|msg_id| AckRequest { msg_id, client_id: reader.clone(), ..Default::default() })
+            .collect::<Vec<_>>();
+        acked += acks.len();
+        client.ack_batch(AckBatchRequest { acks }).await?;
+    }
+
+    let start = Instant::now();
+    for _ in 0..FETCHES {
+        fetch(client, &reader, 0).await?;
+    }
+    Ok(start.elapsed() / FETCHES)
+}
+
+async fn fetch(client: &mut BrokerServiceClient<Channel>, client_id: &str, max_msgs: u32) -> Result<Vec<String>, tonic::Status> {
+    let request = FetchRequest { client_id: client_id.to_string(), max_msgs, ..Default::default() };
+    let response = client.fetch(request).await?.into_inner();
+    Ok(response.msgs.into_iter().map(|msg| msg.id).collect())
+}
@@ -1,14 +1,30 @@
-use crate::broker_service::{ProtoBroker, ProtoAckedMsgs};
-use crate::topic::Topic;
+use crate::broker_service::{ProtoBroker, ProtoPatternSubscribers, ProtoJournalEntry, SubscribeRequest, UnsubscribeRequest, ProtoTruncate, ProtoExpire, ProtoCommit, ProtoCommittedMsg, ProtoDeleteTopic, ProtoAck, ProtoAcks};
+use crate::broker_service::proto_journal_entry::Op;
+use crate::filter::Filter;
+use crate::memory_storage::MemoryStorage;
+use crate::storage::Storage;
+use crate::topic::{FetchBudget, Retention, Topic, TopicConfig, RESIDENT_WINDOW};
+use crate::transaction::Transaction;
 use crate::msg::Msg;
-use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use crate::utils::now_millis;
+use crate::wildcard;
+use std::collections::HashMap;
+use std::sync::Arc;
 use thiserror::Error;
-use prost::Message;
-use std::fs::File;
-use std::io::{Error, ErrorKind, Read, Write};
-use tonic::codegen::Body;
-use tracing::{info};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Number of journal entries after which the full state is snapshotted.
+const SNAPSHOT_INTERVAL: u64 = 1000;
+/// Milliseconds after which a transaction that has been neither committed nor aborted is
+/// aborted by the broker.
+const TRANSACTION_TIMEOUT_MS: u64 = 60_000;
+/// Prefix of the names of the reply topics the broker manages for clients.
+pub const REPLY_TOPIC_PREFIX: &str = "_reply.";
+/// Milliseconds after which a reply topic that has been neither posted to nor fetched from by
+/// its owner is deleted.
+const REPLY_TOPIC_IDLE_MS: u64 = 300_000;
 
 #[derive(Debug, Error)]
 pub enum BrokerError {
@@ -16,75 +32,677 @@ pub enum BrokerError {
     TopicAlreadyExists(String),
     #[error("Topic '{0}' not found")]
     TopicNotFound(String),
+    #[error("Invalid configuration for topic '{0}': {1}")]
+    InvalidTopicConfig(String, String),
     #[error("Message '{0}' not found")]
     MessageNotFound(String),
+    #[error("Message '{0}' is not leased to client '{1}'")]
+    LeaseNotFound(String, String),
+    #[error("Message '{0}' is not pending for client '{1}'")]
+    NotPending(String, String),
+    #[error("Client '{1}' is not subscribed to '{0}'")]
+    NotSubscribed(String, String),
+    #[error("Invalid filter '{0}': {1}")]
+    InvalidFilter(String, String),
+    #[error("Invalid pattern '{0}': {1}")]
+    InvalidPattern(String, String),
+    #[error("Transaction '{0}' not found")]
+    TransactionNotFound(String),
+    #[error("Storage error: {0}")]
+    Storage(#[from] std::io::Error),
+}
+
+/// Where a posted message ended up.
+#[derive(Debug)]
+pub struct Posted {
+    pub offset: u64,
+    /// Whether the post repeated an earlier one, at `offset`, and was not stored again.
+    pub duplicate: bool,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug)]
 pub struct Broker {
     pub topics: HashMap<String, Topic>,
-    pub acked_msgs: HashMap<String, HashSet<String>>
+    /// Topic and offset of every resident message, and of every message delivered from past
+    /// the resident window of its topic, by message id.
+    pub msg_index: HashMap<String, (String, u64)>,
+    /// Open transactions, by id.
+    pub transactions: HashMap<String, Transaction>,
+    /// Clients subscribed to each wildcard pattern, with their filters.
+    pub patterns: HashMap<String, HashMap<String, Option<Filter>>>,
+    /// Notified whenever messages are added to a topic, to wake up waiting fetches.
+    pub posted: Arc<Notify>,
+    /// Fetches made by each client subscribed to any topic, to rotate the topic they start
+    /// at. Not persisted.
+    fetch_turns: HashMap<String, usize>,
+    /// A token per client awaiting a reply, cloned by every wait; its reply topic is kept
+    /// while any clone is alive. Not persisted.
+    reply_waiters: HashMap<String, Arc<()>>,
+    journal_seq: u64,
+    snapshot_seq: u64,
+    replaying: bool,
+    storage: Box<dyn Storage>,
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Broker {
+    /// A broker backed by `MemoryStorage`, so nothing it holds survives a restart.
     pub fn new() -> Self {
         Self {
             topics: HashMap::new(),
-            acked_msgs: HashMap::new(),
+            msg_index: HashMap::new(),
+            transactions: HashMap::new(),
+            patterns: HashMap::new(),
+            posted: Arc::new(Notify::new()),
+            fetch_turns: HashMap::new(),
+            reply_waiters: HashMap::new(),
+            journal_seq: 0,
+            snapshot_seq: 0,
+            replaying: false,
+            storage: Box::new(MemoryStorage::default()),
         }
     }
 
-    pub async fn create_topic(&mut self, name: &str) -> Result<(), BrokerError> {
-        match self.topics.entry(name.to_string()) {
-            Entry::Vacant(entry) => {
-                entry.insert(Topic::new(name));
-                Ok(())
+    /// Restores the broker from the latest snapshot in `storage`, reloads topic messages and
+    /// replays the journal entries written after the snapshot. Every later mutation is
+    /// journalled to `storage`.
+    pub async fn open(mut storage: Box<dyn Storage>) -> Result<Self, std::io::Error> {
+        let (mut broker, legacy_acks) = match storage.load_snapshot()? {
+            Some(mut proto) => {
+                let legacy_acks = std::mem::take(&mut proto.acked_msgs);
+                (Self::from_proto(proto), legacy_acks)
+            }
+            None => (Broker::new(), HashMap::new()),
+        };
+        let entries = storage.load_journal()?;
+        broker.storage = storage;
+
+        let names: Vec<String> = broker.topics.keys().cloned().collect();
+        for name in &names {
+            broker.load_topic(name)?;
+        }
+        if !legacy_acks.is_empty() {
+            for (msg_id, acked) in legacy_acks {
+                let Some((topic_name, offset)) = broker.msg_index.get(&msg_id) else {
+                    continue;
+                };
+                if let Some(topic) = broker.topics.get_mut(topic_name) {
+                    for client_id in &acked.messages {
+                        topic.ack(client_id, *offset);
+                    }
+                }
+            }
+            for name in &names {
+                broker.evict_acked(name, None)?;
+            }
+        }
+
+        broker.replaying = true;
+        let mut replayed = 0;
+        for entry in entries {
+            if entry.seq <= broker.journal_seq {
+                continue;
+            }
+            if entry.seq != broker.journal_seq + 1 {
+                warn!("Journal entries {} to {} are missing", broker.journal_seq + 1, entry.seq - 1);
+            }
+            broker.journal_seq = entry.seq;
+            if let Err(e) = broker.replay(entry).await {
+                warn!("Skipping journal entry {}: {}", broker.journal_seq, e);
+            }
+            replayed += 1;
+        }
+        broker.replaying = false;
+        info!("Replayed {} journal entries", replayed);
+        Ok(broker)
+    }
+
+    /// Opens the message storage of topic `name` and loads its resident window. Messages
+    /// inlined by a snapshot taken before topics had their own storage are moved into it.
+    /// The keys of messages posted within the dedup window are remembered again, as posts
+    /// since the snapshot are not journalled.
+    fn load_topic(&mut self, name: &str) -> Result<(), std::io::Error> {
+        self.storage.open_topic(name)?;
+        let Some(topic) = self.topics.get_mut(name) else {
+            return Ok(());
+        };
+
+        if self.storage.next_offset(name) == 0 && !topic.msgs.is_empty() {
+            for msg in &mut topic.msgs {
+                msg.timestamp = now_millis();
+                msg.offset = self.storage.append_msg(name, msg)?;
+            }
+        } else {
+            topic.start_offset = topic.start_offset.max(self.storage.start_offset(name));
+            topic.resident_from = topic.resident_from.max(topic.start_offset);
+            for cursor in topic.subscribers.values_mut() {
+                cursor.advance_to(topic.start_offset);
             }
-            Entry::Occupied(_) => Err(BrokerError::TopicAlreadyExists(name.to_string())),
+            topic.msgs.clear();
+            topic.resident_until = topic.resident_from;
         }
+        topic.next_offset = self.storage.next_offset(name);
+        if topic.msgs.is_empty() {
+            topic.refill(&*self.storage)?;
+        } else {
+            topic.resident_until = topic.next_offset;
+            topic.resident_from = topic.msgs.front().map_or(topic.next_offset, |msg| msg.offset);
+        }
+
+        let now = now_millis();
+        let recent = Retention { max_age_ms: topic.dedup_window_ms(), ..Retention::default() };
+        let mut from = self.storage.retention_start(name, &recent, now)?;
+        loop {
+            let msgs = self.storage.read_msgs(name, from, RESIDENT_WINDOW)?;
+            let Some(last) = msgs.last() else {
+                break;
+            };
+            from = last.offset + 1;
+            for msg in &msgs {
+                topic.remember(msg, now);
+            }
+        }
+
+        for msg in &topic.msgs {
+            self.msg_index.insert(msg.id.clone(), (name.to_string(), msg.offset));
+        }
+        self.evict_acked(name, None)
     }
 
-    pub async fn subscribe(&mut self, topic_name: &str, client_id: &str) -> Result<(), BrokerError> {
-        match self.topics.get_mut(topic_name) {
-            Some(topic) => {
-                topic.subscribers.insert(client_id.to_string());
+    /// Evicts the acked messages of topic `name`, only looking at the leading ones and the
+    /// one at offset `acked` if given. A `delete_on_ack` topic also has them deleted from
+    /// storage, as far as the oldest message still pending.
+    fn evict_acked(&mut self, name: &str, acked: Option<u64>) -> Result<(), std::io::Error> {
+        let Some(topic) = self.topics.get_mut(name) else {
+            return Ok(());
+        };
+        let evicted = match acked {
+            Some(offset) => topic.evict_acked_at(offset),
+            None => topic.evict_acked(),
+        };
+        for msg_id in &evicted {
+            self.msg_index.remove(msg_id);
+        }
+        self.refill(name)?;
+        let Some(topic) = self.topics.get(name) else {
+            return Ok(());
+        };
+        if topic.config.delete_on_ack && topic.resident_from > self.storage.start_offset(name) {
+            self.storage.truncate(name, topic.resident_from)?;
+        }
+        Ok(())
+    }
+
+    /// Tops up the resident window of topic `name` from storage if eviction has drained it.
+    fn refill(&mut self, name: &str) -> Result<(), std::io::Error> {
+        let Some(topic) = self.topics.get_mut(name) else {
+            return Ok(());
+        };
+        for (msg_id, offset) in topic.refill(&*self.storage)? {
+            self.msg_index.insert(msg_id, (name.to_string(), offset));
+        }
+        Ok(())
+    }
+
+    async fn replay(&mut self, entry: ProtoJournalEntry) -> Result<(), BrokerError> {
+        match entry.op {
+            Some(Op::CreateTopic(req)) => self.create_topic(&req.name, TopicConfig::from_request(&req)).await,
+            Some(Op::Subscribe(req)) => self.subscribe(&req.topic_name, &req.client_id, &req.filter).await,
+            Some(Op::Unsubscribe(req)) => self.unsubscribe(&req.topic_name, &req.client_id).await,
+            Some(Op::Truncate(req)) => self.truncate(&req.topic_name, req.start_offset).await,
+            Some(Op::Expire(req)) => self.expire(&req.topic_name, req.offsets).await,
+            Some(Op::Commit(commit)) => self.replay_commit(commit).await,
+            Some(Op::DeleteTopic(req)) => self.delete_topic(&req.name).await,
+            Some(Op::Acks(req)) => {
+                for ack in req.acks {
+                    self.apply_ack(&ack.topic_name, ack.offset, &ack.client_id)?;
+                }
                 Ok(())
             }
-            None => Err(BrokerError::TopicNotFound(topic_name.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends `op` to the journal before it is applied. Does nothing while the journal
+    /// itself is being replayed.
+    async fn record(&mut self, op: Op) -> Result<(), std::io::Error> {
+        if self.replaying {
+            return Ok(());
+        }
+        if self.journal_seq - self.snapshot_seq >= SNAPSHOT_INTERVAL {
+            self.snapshot().await?;
+        }
+
+        self.journal_seq += 1;
+        let entry = ProtoJournalEntry { seq: self.journal_seq, op: Some(op) };
+        self.storage.append_journal(&entry)?;
+        Ok(())
+    }
+
+    /// Hands back the storage, as a restarted broker would find it.
+    #[cfg(test)]
+    pub fn into_storage(self) -> Box<dyn Storage> {
+        self.storage
+    }
+
+    /// Writes the full state to storage, superseding the journal entries before it.
+    pub async fn snapshot(&mut self) -> Result<(), std::io::Error> {
+        self.storage.write_snapshot(&self.to_proto())?;
+        self.snapshot_seq = self.journal_seq;
+        info!("Snapshot written at journal entry {}", self.journal_seq);
+        Ok(())
+    }
+
+    /// Creates topic `name`, subscribing the clients whose wildcard patterns match it unless
+    /// it is a reply topic.
+    pub async fn create_topic(&mut self, name: &str, config: TopicConfig) -> Result<(), BrokerError> {
+        if self.topics.contains_key(name) {
+            return Err(BrokerError::TopicAlreadyExists(name.to_string()));
+        }
+        let invalid = |reason: &str| Err(BrokerError::InvalidTopicConfig(name.to_string(), reason.to_string()));
+        if wildcard::is_pattern(name) {
+            return invalid("topic names cannot have '*' or '#' levels");
+        }
+        let reply_name = config.reply_owner.as_ref().map(|owner| format!("{}{}", REPLY_TOPIC_PREFIX, owner));
+        if name.starts_with(REPLY_TOPIC_PREFIX) && reply_name.as_deref() != Some(name) {
+            return invalid("names starting with '_reply.' are reserved for reply topics");
+        }
+        if reply_name.is_some_and(|reply_name| reply_name != name) {
+            return invalid("a reply topic must be named after its owner");
+        }
+        if let Some(policy) = &config.dead_letter {
+            if policy.topic.is_empty() || policy.topic == name {
+                return invalid("the dead-letter topic must be another topic");
+            }
+            if wildcard::is_pattern(&policy.topic) {
+                return invalid("the dead-letter topic cannot have '*' or '#' levels");
+            }
+            if policy.topic.starts_with(REPLY_TOPIC_PREFIX) {
+                return invalid("the dead-letter topic cannot be a reply topic");
+            }
+            if policy.max_attempts == 0 {
+                return invalid("max_attempts must be at least 1");
+            }
+        }
+        if let Some(expiry_topic) = &config.expiry_topic {
+            if expiry_topic == name {
+                return invalid("the expiry topic must be another topic");
+            }
+            if wildcard::is_pattern(expiry_topic) {
+                return invalid("the expiry topic cannot have '*' or '#' levels");
+            }
+            if expiry_topic.starts_with(REPLY_TOPIC_PREFIX) {
+                return invalid("the expiry topic cannot be a reply topic");
+            }
+        }
+        self.record(Op::CreateTopic(config.to_request(name))).await?;
+        let mut topic = Topic::new(name, config);
+        if topic.config.reply_owner.is_none() {
+            for (pattern, client_ids) in &self.patterns {
+                if wildcard::matches(pattern, name) {
+                    for (client_id, filter) in client_ids {
+                        topic.subscribe(client_id, filter.clone(), false);
+                    }
+                }
+            }
+        }
+        self.topics.insert(name.to_string(), topic);
+        self.load_topic(name)?;
+        Ok(())
+    }
+
+    /// Deletes topic `name` together with its subscriptions and messages.
+    async fn delete_topic(&mut self, name: &str) -> Result<(), BrokerError> {
+        if !self.topics.contains_key(name) {
+            return Err(BrokerError::TopicNotFound(name.to_string()));
+        }
+        self.record(Op::DeleteTopic(ProtoDeleteTopic { name: name.to_string() })).await?;
+        if let Some(topic) = self.topics.remove(name) {
+            for msg_id in topic.msgs.iter().map(|msg| &msg.id).chain(topic.paged.values()) {
+                self.msg_index.remove(msg_id);
+            }
+            for client_id in topic.subscribers.keys() {
+                self.forget_fetch_turn(client_id);
+            }
+        }
+        // A replayed deletion already had its storage deleted, and the storage found under
+        // the name now belongs to a topic created again later.
+        if !self.replaying {
+            self.storage.delete_topic(name)?;
+        }
+        info!("Topic '{}' deleted", name);
+        Ok(())
+    }
+
+    /// Subscribes `client_id` to topic `topic_name`, or to every topic matching it if it is a
+    /// wildcard pattern, except for reply topics. A non-empty `filter` restricts the messages
+    /// delivered to the client. A client has one filter per topic: subscribing again to a
+    /// topic, by name or through another pattern, replaces the filter it had there.
+    pub async fn subscribe(&mut self, topic_name: &str, client_id: &str, filter: &str) -> Result<(), BrokerError> {
+        if !wildcard::is_pattern(topic_name) && !self.topics.contains_key(topic_name) {
+            return Err(BrokerError::TopicNotFound(topic_name.to_string()));
+        }
+        if wildcard::multi_level_wildcards(topic_name) > wildcard::MAX_MULTI_LEVEL_WILDCARDS {
+            let reason = format!("more than {} '#' levels", wildcard::MAX_MULTI_LEVEL_WILDCARDS);
+            return Err(BrokerError::InvalidPattern(topic_name.to_string(), reason));
+        }
+        let filter = match filter {
+            "" => None,
+            source => Some(Filter::parse(source).map_err(|e| BrokerError::InvalidFilter(source.to_string(), e))?),
+        };
+        self.record(Op::Subscribe(SubscribeRequest {
+            topic_name: topic_name.to_string(),
+            client_id: client_id.to_string(),
+            filter: filter.as_ref().map(|filter| filter.source().to_string()).unwrap_or_default(),
+        })).await?;
+        if wildcard::is_pattern(topic_name) {
+            for (name, topic) in &mut self.topics {
+                if topic.config.reply_owner.is_none() && wildcard::matches(topic_name, name) {
+                    topic.subscribe(client_id, filter.clone(), false);
+                }
+            }
+            self.patterns.entry(topic_name.to_string()).or_default().insert(client_id.to_string(), filter);
+        } else if let Some(topic) = self.topics.get_mut(topic_name) {
+            topic.subscribe(client_id, filter, true);
         }
+        Ok(())
     }
 
+    /// Unsubscribes `client_id` from topic `topic_name`. A wildcard pattern stops subscribing
+    /// the client to new topics, and ends its subscriptions to the matching ones, except to
+    /// those it also subscribed to by name or through another of its patterns.
     pub async fn unsubscribe(&mut self, topic_name: &str, client_id: &str) -> Result<(), BrokerError> {
-        match self.topics.get_mut(topic_name) {
-            Some(topic) => {
+        let subscribed_pattern = self.patterns.get(topic_name).is_some_and(|client_ids| client_ids.contains_key(client_id));
+        if wildcard::is_pattern(topic_name) && !subscribed_pattern {
+            return Err(BrokerError::NotSubscribed(topic_name.to_string(), client_id.to_string()));
+        }
+        if !wildcard::is_pattern(topic_name) && !self.topics.contains_key(topic_name) {
+            return Err(BrokerError::TopicNotFound(topic_name.to_string()));
+        }
+        self.record(Op::Unsubscribe(UnsubscribeRequest {
+            topic_name: topic_name.to_string(),
+            client_id: client_id.to_string(),
+        })).await?;
+        if !wildcard::is_pattern(topic_name) {
+            if let Some(topic) = self.topics.get_mut(topic_name) {
                 topic.subscribers.remove(client_id);
-                Ok(())
             }
-            None => Err(BrokerError::TopicNotFound(topic_name.to_string())),
+            self.forget_fetch_turn(client_id);
+            self.evict_acked(topic_name, None)?;
+            return Ok(());
         }
+
+        if let Some(client_ids) = self.patterns.get_mut(topic_name) {
+            client_ids.remove(client_id);
+            if client_ids.is_empty() {
+                self.patterns.remove(topic_name);
+            }
+        }
+        let remaining: Vec<&String> = self.patterns
+            .iter()
+            .filter(|(_, client_ids)| client_ids.contains_key(client_id))
+            .map(|(pattern, _)| pattern)
+            .collect();
+        let mut matching = vec![];
+        for (name, topic) in &mut self.topics {
+            if topic.config.reply_owner.is_some() || !wildcard::matches(topic_name, name) {
+                continue;
+            }
+            let direct = topic.subscribers.get(client_id).is_some_and(|cursor| cursor.direct);
+            if direct || remaining.iter().any(|pattern| wildcard::matches(pattern, name)) {
+                continue;
+            }
+            topic.subscribers.remove(client_id);
+            matching.push(name.clone());
+        }
+        self.forget_fetch_turn(client_id);
+        for name in matching {
+            self.evict_acked(&name, None)?;
+        }
+        Ok(())
     }
 
-    pub async fn post(&mut self, topic_name: &str, payload: &str) -> Result<(), BrokerError> {
-        match self.topics.get_mut(topic_name) {
-            Some(topic) => {
-                let msg = Msg::new(payload);
-                let msg_id = msg.id.clone();
-                self.acked_msgs.insert(msg_id, HashSet::new());
-                topic.msgs.push(msg);
-                Ok(())
+    /// Forgets the fetches made by `client_id` once it subscribes to no topic.
+    fn forget_fetch_turn(&mut self, client_id: &str) {
+        if !self.topics.values().any(|topic| topic.subscribers.contains_key(client_id)) {
+            self.fetch_turns.remove(client_id);
+        }
+    }
+
+    /// Posts `msg` to `topic_name`. A post repeating one made within the dedup window is not
+    /// stored again, and gets the offset of the earlier one.
+    pub async fn post(&mut self, topic_name: &str, mut msg: Msg) -> Result<Posted, BrokerError> {
+        let now = now_millis();
+        let Some(topic) = self.topics.get(topic_name) else {
+            return Err(BrokerError::TopicNotFound(topic_name.to_string()));
+        };
+        if let Some(offset) = topic.posted_offset(&msg, now) {
+            return Ok(Posted { offset, duplicate: true });
+        }
+        msg.offset = self.storage.append_msg(topic_name, &msg)?;
+        let offset = msg.offset;
+        self.add_stored(topic_name, msg, now);
+        Ok(Posted { offset, duplicate: false })
+    }
+
+    /// Posts every message of `posts` to its topic, storing the messages of each topic with a
+    /// single write, and returns the outcome of each post in order. Posts repeating an earlier
+    /// one, within the dedup window or the batch, are not stored again; a post repeating one of
+    /// the batch shares its outcome.
+    pub async fn post_batch(&mut self, posts: Vec<(String, Msg)>) -> Vec<Result<Posted, BrokerError>> {
+        let now = now_millis();
+        let mut results = Vec::with_capacity(posts.len());
+        let mut batches: HashMap<String, Vec<(usize, Msg)>> = HashMap::new();
+        // Index in `results` of the post first carrying each key, and the posts repeating it.
+        let mut batch_keys: HashMap<(String, String), usize> = HashMap::new();
+        let mut repeats = Vec::new();
+        for (topic_name, mut msg) in posts {
+            let Some(topic) = self.topics.get(&topic_name) else {
+                results.push(Err(BrokerError::TopicNotFound(topic_name)));
+                continue;
+            };
+            if let Some(offset) = topic.posted_offset(&msg, now) {
+                results.push(Ok(Posted { offset, duplicate: true }));
+                continue;
+            }
+            let key = msg.dedup_key().map(|key| (topic_name.clone(), key));
+            if let Some(&first) = key.as_ref().and_then(|key| batch_keys.get(key)) {
+                // Settled once the post it repeats has been stored.
+                results.push(Ok(Posted { offset: 0, duplicate: true }));
+                repeats.push((results.len() - 1, first));
+                continue;
+            }
+            let batch = batches.entry(topic_name.clone()).or_default();
+            msg.offset = self.storage.next_offset(&topic_name) + batch.len() as u64;
+            results.push(Ok(Posted { offset: msg.offset, duplicate: false }));
+            if let Some(key) = key {
+                batch_keys.insert(key, results.len() - 1);
             }
-            None => Err(BrokerError::TopicNotFound(topic_name.to_string())),
+            batch.push((results.len() - 1, msg));
+        }
+
+        for (topic_name, batch) in batches {
+            let (indices, msgs): (Vec<usize>, Vec<Msg>) = batch.into_iter().unzip();
+            if let Err(e) = self.storage.append_msgs(&topic_name, &msgs) {
+                for i in indices {
+                    results[i] = Err(BrokerError::Storage(std::io::Error::new(e.kind(), e.to_string())));
+                }
+                continue;
+            }
+            for msg in msgs {
+                self.add_stored(&topic_name, msg, now);
+            }
+        }
+
+        for (i, first) in repeats {
+            results[i] = match &results[first] {
+                Ok(posted) => Ok(Posted { offset: posted.offset, duplicate: true }),
+                Err(BrokerError::Storage(e)) => Err(BrokerError::Storage(std::io::Error::new(e.kind(), e.to_string()))),
+                Err(e) => Err(BrokerError::Storage(std::io::Error::other(e.to_string()))),
+            };
+        }
+        results
+    }
+
+    /// Makes `msg`, just stored, part of topic `topic_name`.
+    fn add_stored(&mut self, topic_name: &str, msg: Msg, now: u64) {
+        if let Some(topic) = self.topics.get_mut(topic_name) {
+            topic.remember(&msg, now);
+            topic.last_active = now;
+            let (msg_id, offset) = (msg.id.clone(), msg.offset);
+            if topic.append(msg) {
+                self.msg_index.insert(msg_id, (topic_name.to_string(), offset));
+            }
+            self.posted.notify_waiters();
         }
     }
 
     pub async fn ack(&mut self, msg_id: &str, client_id: &str) -> Result<(), BrokerError> {
-        match self.acked_msgs.get_mut(msg_id) {
-            None => Err(BrokerError::MessageNotFound(msg_id.to_string())),
-            Some(msg) => {
-                msg.insert(client_id.to_string());
-                Ok(())
-            },
+        let Some((topic_name, offset)) = self.msg_index.get(msg_id).cloned() else {
+            return Err(BrokerError::MessageNotFound(msg_id.to_string()));
+        };
+        self.record(Op::Acks(ProtoAcks {
+            acks: vec![ProtoAck { topic_name: topic_name.clone(), offset, client_id: client_id.to_string() }],
+        })).await?;
+        self.apply_ack(&topic_name, offset, client_id)?;
+        Ok(())
+    }
+
+    /// Acks every message of `acks`, given as message and client ids, journalling them as a
+    /// single entry, and returns the outcome of each ack in order.
+    pub async fn ack_batch(&mut self, acks: Vec<(String, String)>) -> Vec<Result<(), BrokerError>> {
+        let mut results = Vec::with_capacity(acks.len());
+        let mut found = vec![];
+        for (msg_id, client_id) in acks {
+            match self.msg_index.get(&msg_id).cloned() {
+                Some((topic_name, offset)) => {
+                    found.push((results.len(), client_id, topic_name, offset));
+                    results.push(Ok(()));
+                }
+                None => results.push(Err(BrokerError::MessageNotFound(msg_id))),
+            }
+        }
+        if found.is_empty() {
+            return results;
         }
+
+        let entry = ProtoAcks {
+            acks: found
+                .iter()
+                .map(|(_, client_id, topic_name, offset)| ProtoAck {
+                    topic_name: topic_name.clone(),
+                    offset: *offset,
+                    client_id: client_id.clone(),
+                })
+                .collect(),
+        };
+        if let Err(e) = self.record(Op::Acks(entry)).await {
+            for (i, ..) in found {
+                results[i] = Err(BrokerError::Storage(std::io::Error::new(e.kind(), e.to_string())));
+            }
+            return results;
+        }
+        for (i, client_id, topic_name, offset) in found {
+            if let Err(e) = self.apply_ack(&topic_name, offset, &client_id) {
+                results[i] = Err(e.into());
+            }
+        }
+        results
+    }
+
+    fn apply_ack(&mut self, topic_name: &str, offset: u64, client_id: &str) -> Result<(), std::io::Error> {
+        if let Some(topic) = self.topics.get_mut(topic_name) {
+            topic.ack(client_id, offset);
+        }
+        self.evict_acked(topic_name, Some(offset))
+    }
+
+    /// Drops the messages that fell out of the retention limits of their topic.
+    pub async fn enforce_retention(&mut self) -> Result<(), BrokerError> {
+        let now = now_millis();
+        let mut truncations = vec![];
+        for (name, topic) in &self.topics {
+            if topic.config.retention.is_unlimited() {
+                continue;
+            }
+            let start = self.storage.retention_start(name, &topic.config.retention, now)?;
+            if start > topic.start_offset {
+                truncations.push((name.clone(), start));
+            }
+        }
+        for (name, start) in truncations {
+            self.truncate(&name, start).await?;
+        }
+        Ok(())
+    }
+
+    async fn truncate(&mut self, topic_name: &str, start_offset: u64) -> Result<(), BrokerError> {
+        if !self.topics.contains_key(topic_name) {
+            return Err(BrokerError::TopicNotFound(topic_name.to_string()));
+        }
+        self.record(Op::Truncate(ProtoTruncate {
+            topic_name: topic_name.to_string(),
+            start_offset,
+        })).await?;
+        self.storage.truncate(topic_name, start_offset)?;
+        if let Some(topic) = self.topics.get_mut(topic_name) {
+            let removed = topic.truncate(start_offset);
+            for msg_id in &removed {
+                self.msg_index.remove(msg_id);
+            }
+            info!("Retention dropped {} messages of '{}' before offset {}", removed.len(), topic_name, start_offset);
+        }
+        self.refill(topic_name)?;
+        Ok(())
+    }
+
+    /// Drops the messages whose time to live has passed, moving those not yet acked by every
+    /// subscriber to the expiry topic of their topic, if it has one.
+    pub async fn expire_msgs(&mut self) -> Result<(), BrokerError> {
+        let now = now_millis();
+        let mut expirations = vec![];
+        for (name, topic) in &self.topics {
+            let (offsets, copies) = topic.expired(now);
+            if !offsets.is_empty() {
+                expirations.push((name.clone(), topic.config.expiry_topic.clone(), offsets, copies));
+            }
+        }
+        for (name, expiry_topic, offsets, copies) in expirations {
+            if let Some(expiry_topic) = expiry_topic {
+                if !copies.is_empty() && !self.topics.contains_key(&expiry_topic) {
+                    self.create_topic(&expiry_topic, TopicConfig::default()).await?;
+                }
+                for msg in copies {
+                    self.post(&expiry_topic, msg).await?;
+                }
+            }
+            self.expire(&name, offsets).await?;
+        }
+        Ok(())
+    }
+
+    async fn expire(&mut self, topic_name: &str, offsets: Vec<u64>) -> Result<(), BrokerError> {
+        if !self.topics.contains_key(topic_name) {
+            return Err(BrokerError::TopicNotFound(topic_name.to_string()));
+        }
+        self.record(Op::Expire(ProtoExpire {
+            topic_name: topic_name.to_string(),
+            offsets: offsets.clone(),
+        })).await?;
+        if let Some(topic) = self.topics.get_mut(topic_name) {
+            let expired = topic.expire(&offsets);
+            for msg_id in &expired {
+                self.msg_index.remove(msg_id);
+            }
+            info!("Expired {} messages of '{}'", offsets.len(), topic_name);
+        }
+        self.evict_acked(topic_name, None)?;
+        Ok(())
     }
 
     fn from_proto(proto: ProtoBroker) -> Self {
@@ -93,18 +711,36 @@ impl Broker {
             .into_iter()
             .map(|(key, topic)| (key, Topic::from_proto(topic)))
             .collect();
-
-        let acked_msgs = proto
-            .acked_msgs
+        let patterns = proto
+            .patterns
             .into_iter()
-            .map(|(key, acked_msgs)| {
-                let messages: HashSet<String> = acked_msgs.messages.into_iter().collect();
-                (key, messages)
+            .map(|(pattern, mut subscribers)| {
+                let client_ids = subscribers
+                    .client_ids
+                    .into_iter()
+                    .map(|client_id| {
+                        let filter = subscribers.filters.remove(&client_id).and_then(|source| Filter::parse(&source).ok());
+                        (client_id, filter)
+                    })
+                    .collect();
+                (pattern, client_ids)
             })
             .collect();
 
-        Broker { topics, acked_msgs }
-}
+        Broker {
+            topics,
+            msg_index: HashMap::new(),
+            transactions: HashMap::new(),
+            patterns,
+            posted: Arc::new(Notify::new()),
+            fetch_turns: HashMap::new(),
+            reply_waiters: HashMap::new(),
+            journal_seq: proto.journal_seq,
+            snapshot_seq: proto.journal_seq,
+            replaying: false,
+            storage: Box::new(MemoryStorage::default()),
+        }
+    }
 
     pub fn to_proto(&self) -> ProtoBroker {
         let topics = self
@@ -115,59 +751,796 @@ impl Broker {
             })
             .collect();
 
-        let acked_msgs = self
-            .acked_msgs
+        let patterns = self
+            .patterns
             .iter()
-            .map(|(key, client_ids)| {
-                let client_ids_vector: Vec<String> = client_ids.iter().cloned().collect();
-                let proto_acked_msgs = ProtoAckedMsgs {
-                    messages: client_ids_vector,
+            .map(|(pattern, client_ids)| {
+                let subscribers = ProtoPatternSubscribers {
+                    client_ids: client_ids.keys().cloned().collect(),
+                    filters: client_ids
+                        .iter()
+                        .filter_map(|(client_id, filter)| Some((client_id.clone(), filter.as_ref()?.source().to_string())))
+                        .collect(),
                 };
-                (key.clone(), proto_acked_msgs)
+                (pattern.clone(), subscribers)
             })
             .collect();
 
-        ProtoBroker { topics, acked_msgs }
+        ProtoBroker { topics, acked_msgs: HashMap::new(), journal_seq: self.journal_seq, patterns }
     }
 
 
-    pub async fn save_to_file(&self, path: &str) -> Result<(), std::io::Error> {
-        let proto = self.to_proto();
-        let mut buf = Vec::new();
-        proto.encode(&mut buf).unwrap();
-        let mut file = File::create(path)?;
-        file.write_all(&buf)?;
+    /// Returns the messages of every topic `client_id` subscribes to that it has not acked
+    /// and holds no lease on, as many as fit into `budget`, leasing them to it for
+    /// `lease_ms`. Messages out of delivery attempts are moved to their dead-letter topic
+    /// instead. Messages not matching the filter of the client are acked for it, like any
+    /// other ack, so that a later change of filter does not bring them back.
+    /// Each fetch of a client starts at the next of its topics, so that a topic with a
+    /// backlog cannot take the whole budget every time.
+    pub async fn fetch(&mut self, client_id: &str, lease_ms: u64, mut budget: FetchBudget) -> Result<Vec<Msg>, BrokerError> {
+        let now = now_millis();
+        let mut msgs = vec![];
+        let mut dead = vec![];
+        let mut filtered_out = vec![];
+        let mut topics: Vec<&mut Topic> = self.topics
+            .values_mut()
+            .filter(|topic| topic.subscribers.contains_key(client_id))
+            .collect();
+        if topics.is_empty() {
+            self.fetch_turns.remove(client_id);
+        } else {
+            let turn = self.fetch_turns.entry(client_id.to_string()).or_default();
+            let start = *turn % topics.len();
+            topics.rotate_left(start);
+            *turn = turn.wrapping_add(1);
+        }
+        for topic in topics {
+            if topic.config.reply_owner.as_deref() == Some(client_id) {
+                topic.last_active = now;
+            }
+            let delivered = topic.deliver(client_id, now, now.saturating_add(lease_ms), &mut budget, &*self.storage)?;
+            for (msg_id, offset) in delivered.paged {
+                self.msg_index.insert(msg_id, (topic.name.clone(), offset));
+            }
+            msgs.extend(delivered.msgs);
+            if let Some(policy) = &topic.config.dead_letter {
+                dead.extend(delivered.dead_lettered.into_iter().map(|msg| (policy.topic.clone(), msg)));
+            }
+            filtered_out.extend(delivered.filtered_out.into_iter().map(|msg_id| (msg_id, client_id.to_string())));
+        }
+        if !filtered_out.is_empty() {
+            self.ack_batch(filtered_out).await.into_iter().collect::<Result<(), _>>()?;
+        }
+        for (dead_letter_topic, msg) in dead {
+            self.dead_letter(&dead_letter_topic, msg).await?;
+        }
+        Ok(msgs)
+    }
+
+    /// Posts `msg` to `topic_name`, creating the topic if needed, and acks the original
+    /// message for the client that gave up on it.
+    async fn dead_letter(&mut self, topic_name: &str, msg: Msg) -> Result<(), BrokerError> {
+        let Some(origin) = msg.dead_lettered.clone() else {
+            return Ok(());
+        };
+        if !self.topics.contains_key(topic_name) {
+            self.create_topic(topic_name, TopicConfig::default()).await?;
+        }
+        let offset = self.post(topic_name, msg).await?.offset;
+        self.ack(&origin.msg_id, &origin.client_id).await?;
+        warn!(
+            "Message {} of '{}' dead-lettered to '{}' at offset {} after {} attempts by '{}'",
+            origin.offset, origin.topic, topic_name, offset, origin.attempts, origin.client_id,
+        );
         Ok(())
     }
 
-    pub async fn load_from_file(path: &str) -> Result<Self, std::io::Error> {
-        let mut file = File::open(path)?;
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf)?;
-        match ProtoBroker::decode(&buf[..]) {
-            Ok(proto) => { Ok(Self::from_proto(proto)) }
-            Err(_) => { Err(Error::new(ErrorKind::InvalidData, "broker state data file corrupted")) }
+    /// Ends every lease `client_id` holds, so that delivery to it resumes after the messages
+    /// it has acked.
+    pub async fn release_leases(&mut self, client_id: &str) {
+        for topic in self.topics.values_mut() {
+            if let Some(cursor) = topic.subscribers.get_mut(client_id) {
+                cursor.release_leases();
+            }
         }
     }
 
-    pub async fn fetch(&self, client_id: &str) -> Vec<&Msg> {
-        let subscribed_topics = self.topics.iter()
-            .filter(|t| t.1.subscribers.contains(&client_id.to_string()));
+    /// Restarts the lease `client_id` holds on message `msg_id`, to expire `lease_ms` from now.
+    pub async fn extend_lease(&mut self, msg_id: &str, client_id: &str, lease_ms: u64) -> Result<(), BrokerError> {
+        let Some((topic_name, offset)) = self.msg_index.get(msg_id) else {
+            return Err(BrokerError::MessageNotFound(msg_id.to_string()));
+        };
+        let now = now_millis();
+        let extended = self.topics
+            .get_mut(topic_name)
+            .is_some_and(|topic| topic.extend_lease(client_id, *offset, now, now.saturating_add(lease_ms)));
+        if !extended {
+            return Err(BrokerError::LeaseNotFound(msg_id.to_string(), client_id.to_string()));
+        }
+        Ok(())
+    }
 
-        let all_msgs: Vec<&Msg> = subscribed_topics
-            .flat_map(|(_key, topic)| topic.msgs.iter())
-            .collect();
+    /// Releases message `msg_id` from `client_id`, to be delivered to it again after
+    /// `delay_ms`. Like leases, nacks are not persisted.
+    pub async fn nack(&mut self, msg_id: &str, client_id: &str, delay_ms: u64, reason: &str) -> Result<(), BrokerError> {
+        let Some((topic_name, offset)) = self.msg_index.get(msg_id) else {
+            return Err(BrokerError::MessageNotFound(msg_id.to_string()));
+        };
+        let redeliver_at = now_millis().saturating_add(delay_ms);
+        let nacked = self.topics
+            .get_mut(topic_name)
+            .is_some_and(|topic| topic.nack(client_id, *offset, redeliver_at, reason));
+        if !nacked {
+            return Err(BrokerError::NotPending(msg_id.to_string(), client_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Reads up to `max` messages of topic `topic_name` from `offset` on, whether acked or
+    /// not. Messages already dropped by retention or deletion are skipped.
+    pub async fn fetch_from(&self, topic_name: &str, offset: u64, max: usize) -> Result<Vec<Msg>, BrokerError> {
+        let topic = self.topics
+            .get(topic_name)
+            .ok_or_else(|| BrokerError::TopicNotFound(topic_name.to_string()))?;
+        let mut from = offset.max(topic.start_offset);
+        if topic.config.delete_on_ack {
+            from = from.max(topic.resident_from);
+        }
+        let mut msgs = self.storage.read_msgs(topic_name, from, max)?;
+        msgs.retain(|msg| !topic.deleted.contains(&msg.offset));
+        Ok(msgs)
+    }
+
+    pub async fn begin_transaction(&mut self) -> String {
+        let transaction_id = Uuid::new_v4().to_string();
+        self.transactions.insert(transaction_id.clone(), Transaction::new(now_millis()));
+        transaction_id
+    }
+
+    /// Adds posting `msg` to `topic_name` to transaction `transaction_id`.
+    pub async fn post_in_transaction(&mut self, transaction_id: &str, topic_name: &str, msg: Msg) -> Result<(), BrokerError> {
+        if !self.topics.contains_key(topic_name) {
+            return Err(BrokerError::TopicNotFound(topic_name.to_string()));
+        }
+        let transaction = self.transactions
+            .get_mut(transaction_id)
+            .ok_or_else(|| BrokerError::TransactionNotFound(transaction_id.to_string()))?;
+        transaction.posts.push((topic_name.to_string(), msg));
+        Ok(())
+    }
 
-        let new_msgs = all_msgs
+    /// Adds acking message `msg_id` for `client_id` to transaction `transaction_id`.
+    pub async fn ack_in_transaction(&mut self, transaction_id: &str, msg_id: &str, client_id: &str) -> Result<(), BrokerError> {
+        if !self.msg_index.contains_key(msg_id) {
+            return Err(BrokerError::MessageNotFound(msg_id.to_string()));
+        }
+        let transaction = self.transactions
+            .get_mut(transaction_id)
+            .ok_or_else(|| BrokerError::TransactionNotFound(transaction_id.to_string()))?;
+        transaction.acks.push((msg_id.to_string(), client_id.to_string()));
+        Ok(())
+    }
+
+    /// Applies the posts and acks of transaction `transaction_id` at once, returning the
+    /// offsets of the posted messages. A post repeating an earlier one, within the dedup window
+    /// or the transaction, is not stored again. The transaction is journalled as a single
+    /// entry, so a restart midway through completes it. If any of its topics or messages is gone, none of
+    /// it is applied and the transaction is aborted.
+    pub async fn commit_transaction(&mut self, transaction_id: &str) -> Result<Vec<u64>, BrokerError> {
+        let transaction = self.transactions
+            .remove(transaction_id)
+            .ok_or_else(|| BrokerError::TransactionNotFound(transaction_id.to_string()))?;
+        let now = now_millis();
+        let mut acks = vec![];
+        for (msg_id, client_id) in &transaction.acks {
+            let Some((topic_name, offset)) = self.msg_index.get(msg_id).cloned() else {
+                return Err(BrokerError::MessageNotFound(msg_id.clone()));
+            };
+            acks.push((topic_name, offset, client_id.clone()));
+        }
+        let mut next_offsets = HashMap::new();
+        let mut commit_keys: HashMap<(String, String), u64> = HashMap::new();
+        let mut msgs = vec![];
+        let mut offsets = vec![];
+        for (topic_name, mut msg) in transaction.posts {
+            let Some(topic) = self.topics.get(&topic_name) else {
+                return Err(BrokerError::TopicNotFound(topic_name));
+            };
+            let key = msg.dedup_key().map(|key| (topic_name.clone(), key));
+            let repeated = topic
+                .posted_offset(&msg, now)
+                .or_else(|| key.as_ref().and_then(|key| commit_keys.get(key).copied()));
+            if let Some(offset) = repeated {
+                offsets.push(offset);
+                continue;
+            }
+            let next = next_offsets.entry(topic_name.clone()).or_insert_with(|| self.storage.next_offset(&topic_name));
+            msg.offset = *next;
+            *next += 1;
+            if let Some(key) = key {
+                commit_keys.insert(key, msg.offset);
+            }
+            offsets.push(msg.offset);
+            msgs.push((topic_name, msg));
+        }
+
+        self.record(Op::Commit(ProtoCommit {
+            msgs: msgs
+                .iter()
+                .map(|(topic_name, msg)| ProtoCommittedMsg {
+                    topic_name: topic_name.clone(),
+                    msg: Some(msg.to_proto()),
+                })
+                .collect(),
+            acks: acks
+                .iter()
+                .map(|(topic_name, offset, client_id)| ProtoAck {
+                    topic_name: topic_name.clone(),
+                    offset: *offset,
+                    client_id: client_id.clone(),
+                })
+                .collect(),
+        })).await?;
+        self.apply_commit(msgs, acks).await?;
+        Ok(offsets)
+    }
+
+    async fn replay_commit(&mut self, commit: ProtoCommit) -> Result<(), BrokerError> {
+        let msgs = commit.msgs
             .into_iter()
-            .filter(|msg| {
-                match self.acked_msgs.get(&msg.id) {
-                    Some (acked_clients) => !acked_clients.contains(client_id),
-                    None => true,
-                }
-            })
+            .map(|committed| (committed.topic_name, Msg::from_proto(committed.msg.unwrap_or_default())))
+            .collect();
+        let acks = commit.acks
+            .into_iter()
+            .map(|ack| (ack.topic_name, ack.offset, ack.client_id))
+            .collect();
+        self.apply_commit(msgs, acks).await
+    }
+
+    /// Posts `msgs` unless already stored at their offsets, and applies `acks`, given as topic,
+    /// offset and client id.
+    async fn apply_commit(&mut self, msgs: Vec<(String, Msg)>, acks: Vec<(String, u64, String)>) -> Result<(), BrokerError> {
+        for (topic_name, msg) in msgs {
+            if msg.offset < self.storage.next_offset(&topic_name) {
+                continue;
+            }
+            self.post(&topic_name, msg).await?;
+        }
+        for (topic_name, offset, client_id) in acks {
+            self.apply_ack(&topic_name, offset, &client_id)?;
+        }
+        Ok(())
+    }
+
+    pub async fn abort_transaction(&mut self, transaction_id: &str) -> Result<(), BrokerError> {
+        match self.transactions.remove(transaction_id) {
+            Some(_) => Ok(()),
+            None => Err(BrokerError::TransactionNotFound(transaction_id.to_string())),
+        }
+    }
+
+    /// Aborts the transactions that have been open for longer than the timeout.
+    pub async fn abort_stale_transactions(&mut self) {
+        let cutoff = now_millis().saturating_sub(TRANSACTION_TIMEOUT_MS);
+        self.transactions.retain(|transaction_id, transaction| {
+            let stale = transaction.started < cutoff;
+            if stale {
+                warn!("Transaction '{}' timed out and was aborted", transaction_id);
+            }
+            !stale
+        });
+    }
+
+    /// Returns the name of the reply topic of `client_id`, creating the topic and subscribing
+    /// the client to it if needed.
+    pub async fn reply_topic(&mut self, client_id: &str) -> Result<String, BrokerError> {
+        let name = format!("{}{}", REPLY_TOPIC_PREFIX, client_id);
+        match self.topics.get_mut(&name) {
+            Some(topic) if topic.config.reply_owner.as_deref() == Some(client_id) => {
+                topic.last_active = now_millis();
+            }
+            Some(_) => return Err(BrokerError::TopicAlreadyExists(name)),
+            None => {
+                let config = TopicConfig {
+                    reply_owner: Some(client_id.to_string()),
+                    ..TopicConfig::default()
+                };
+                self.create_topic(&name, config).await?;
+                self.subscribe(&name, client_id, "").await?;
+            }
+        }
+        Ok(name)
+    }
+
+    /// Marks `client_id` as awaiting a reply until the returned token is dropped, so that its
+    /// reply topic is not deleted meanwhile.
+    pub async fn await_reply(&mut self, client_id: &str) -> Arc<()> {
+        self.reply_waiters.entry(client_id.to_string()).or_default().clone()
+    }
+
+    /// Whether a client is awaiting a reply on its reply topic, forgetting it otherwise.
+    fn awaits_reply(&mut self, client_id: &str) -> bool {
+        let waiting = self.reply_waiters.get(client_id).is_some_and(|token| Arc::strong_count(token) > 1);
+        if !waiting {
+            self.reply_waiters.remove(client_id);
+        }
+        waiting
+    }
+
+    /// Takes the reply with `correlation_id` that `client_id` has not acked yet off its reply
+    /// topic `topic_name`, acking it.
+    pub async fn take_reply(&mut self, topic_name: &str, client_id: &str, correlation_id: &str) -> Result<Option<Msg>, BrokerError> {
+        let topic = self.topics
+            .get_mut(topic_name)
+            .ok_or_else(|| BrokerError::TopicNotFound(topic_name.to_string()))?;
+        topic.last_active = now_millis();
+        let Some(reply) = topic.find_reply(client_id, correlation_id).cloned() else {
+            return Ok(None);
+        };
+        self.ack(&reply.id, client_id).await?;
+        Ok(Some(reply))
+    }
+
+    /// Deletes the reply topic of `client_id`, if it has one and is not awaiting a reply on it.
+    /// A topic kept for a reply is deleted once idle.
+    pub async fn delete_reply_topic(&mut self, client_id: &str) -> Result<(), BrokerError> {
+        let name = format!("{}{}", REPLY_TOPIC_PREFIX, client_id);
+        let owned = self.topics
+            .get(&name)
+            .is_some_and(|topic| topic.config.reply_owner.as_deref() == Some(client_id));
+        if owned && !self.awaits_reply(client_id) {
+            self.delete_topic(&name).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the reply topics that have been idle for longer than the timeout, unless their
+    /// owner is awaiting a reply.
+    pub async fn delete_idle_reply_topics(&mut self) -> Result<(), BrokerError> {
+        let cutoff = now_millis().saturating_sub(REPLY_TOPIC_IDLE_MS);
+        let idle: Vec<(String, String)> = self.topics
+            .iter()
+            .filter(|(_, topic)| topic.last_active < cutoff)
+            .filter_map(|(name, topic)| Some((name.clone(), topic.config.reply_owner.clone()?)))
             .collect();
+        for (name, owner) in idle {
+            if self.awaits_reply(&owner) {
+                continue;
+            }
+            self.delete_topic(&name).await?;
+        }
+        Ok(())
+    }
+}
 
-        new_msgs
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_storage::FileStorage;
+    use crate::sqlite_storage::SqliteStorage;
+    use crate::topic::DeadLetterPolicy;
+    use crate::utils::TempDir;
+    use std::time::Duration;
+
+    fn payloads(broker: &Broker, msgs: &[Msg], topic_name: &str) -> Vec<String> {
+        let mut payloads: Vec<String> = msgs
+            .iter()
+            .filter(|msg| broker.msg_index[&msg.id].0 == topic_name)
+            .map(|msg| String::from_utf8_lossy(&msg.payload).into_owned())
+            .collect();
+        payloads.sort();
+        payloads
     }
-}
\ No newline at end of file
+
+    /// Fills a queue topic and a log topic, acks the whole queue and part of the log, then
+    /// restarts the broker on the storage `reopen` returns and checks that it carries on where
+    /// it left off.
+    async fn check_restart(storage: Box<dyn Storage>, reopen: impl FnOnce(Box<dyn Storage>) -> Box<dyn Storage>) {
+        let mut broker = Broker::open(storage).await.unwrap();
+        let queue = TopicConfig { delete_on_ack: true, ..TopicConfig::default() };
+        broker.create_topic("queue", queue).await.unwrap();
+        broker.create_topic("log", TopicConfig::default()).await.unwrap();
+        for topic_name in ["queue", "log"] {
+            broker.subscribe(topic_name, "c", "").await.unwrap();
+            for i in 0..3 {
+                broker.post(topic_name, Msg::new(format!("m{}", i).as_bytes())).await.unwrap();
+            }
+        }
+        let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(fetched.len(), 6);
+        for msg in &fetched {
+            if broker.msg_index[&msg.id].0 == "queue" {
+                broker.ack(&msg.id, "c").await.unwrap();
+            }
+        }
+        broker.snapshot().await.unwrap();
+        let first_logged = fetched
+            .iter()
+            .find(|msg| broker.msg_index.get(&msg.id) == Some(&("log".to_string(), 0)))
+            .unwrap();
+        broker.ack(&first_logged.id, "c").await.unwrap();
+
+        let mut broker = Broker::open(reopen(broker.into_storage())).await.unwrap();
+        assert_eq!(broker.post("queue", Msg::new(b"after")).await.unwrap().offset, 3);
+        assert_eq!(broker.post("log", Msg::new(b"after")).await.unwrap().offset, 3);
+        let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(payloads(&broker, &fetched, "queue"), ["after"]);
+        assert_eq!(payloads(&broker, &fetched, "log"), ["after", "m1", "m2"]);
+    }
+
+    #[tokio::test]
+    async fn filtered_out_msgs_stay_acked_after_filter_change_and_restart() {
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        broker.subscribe("t", "c", "headers.kind = 'a'").await.unwrap();
+        for kind in ["a", "b"] {
+            let mut msg = Msg::new(kind.as_bytes());
+            msg.headers.insert("kind".to_string(), kind.to_string());
+            broker.post("t", msg).await.unwrap();
+        }
+        let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(payloads(&broker, &fetched, "t"), ["a"]);
+
+        broker.subscribe("t", "c", "headers.kind = 'b'").await.unwrap();
+        let mut broker = Broker::open(broker.into_storage()).await.unwrap();
+        let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(payloads(&broker, &fetched, "t"), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn rejects_wildcard_topic_names() {
+        let mut broker = Broker::new();
+        let rejected = |result: Result<(), BrokerError>| matches!(result, Err(BrokerError::InvalidTopicConfig(..)));
+        assert!(rejected(broker.create_topic("orders.#", TopicConfig::default()).await));
+        let dead_letter = DeadLetterPolicy { topic: "dlq.*".to_string(), max_attempts: 3 };
+        let config = TopicConfig { dead_letter: Some(dead_letter), ..TopicConfig::default() };
+        assert!(rejected(broker.create_topic("orders", config).await));
+        let config = TopicConfig { expiry_topic: Some("expired/#".to_string()), ..TopicConfig::default() };
+        assert!(rejected(broker.create_topic("orders", config).await));
+        assert!(broker.topics.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reserves_reply_topic_names() {
+        let mut broker = Broker::new();
+        let rejected = |result: Result<(), BrokerError>| matches!(result, Err(BrokerError::InvalidTopicConfig(..)));
+        assert!(rejected(broker.create_topic("_reply.victim", TopicConfig::default()).await));
+        let dead_letter = DeadLetterPolicy { topic: "_reply.victim".to_string(), max_attempts: 3 };
+        let config = TopicConfig { dead_letter: Some(dead_letter), ..TopicConfig::default() };
+        assert!(rejected(broker.create_topic("orders", config).await));
+        let config = TopicConfig { expiry_topic: Some("_reply.victim".to_string()), ..TopicConfig::default() };
+        assert!(rejected(broker.create_topic("orders", config).await));
+        let config = TopicConfig { reply_owner: Some("victim".to_string()), ..TopicConfig::default() };
+        assert!(rejected(broker.create_topic("_reply.other", config).await));
+        assert!(broker.topics.is_empty());
+
+        assert_eq!(broker.reply_topic("victim").await.unwrap(), "_reply.victim");
+    }
+
+    #[tokio::test]
+    async fn keeps_the_reply_topic_of_a_client_awaiting_a_reply() {
+        let mut broker = Broker::new();
+        let name = broker.reply_topic("c").await.unwrap();
+        let waiting = broker.await_reply("c").await;
+        broker.delete_reply_topic("c").await.unwrap();
+        assert!(broker.topics.contains_key(&name));
+
+        drop(waiting);
+        broker.delete_reply_topic("c").await.unwrap();
+        assert!(!broker.topics.contains_key(&name));
+        assert!(broker.reply_waiters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn delivers_past_the_resident_window() {
+        let total = RESIDENT_WINDOW + 20;
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        broker.subscribe("t", "fast", "").await.unwrap();
+        broker.subscribe("t", "slow", "").await.unwrap();
+        for i in 0..total {
+            broker.post("t", Msg::new(i.to_string().as_bytes())).await.unwrap();
+        }
+        assert_eq!(broker.topics["t"].msgs.len(), RESIDENT_WINDOW);
+
+        let fetched = broker.fetch("fast", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(fetched.len(), total);
+        for msg in &fetched {
+            broker.ack(&msg.id, "fast").await.unwrap();
+        }
+
+        let mut broker = Broker::open(broker.into_storage()).await.unwrap();
+        assert!(broker.fetch("fast", 60_000, FetchBudget::new(0, 0)).await.unwrap().is_empty());
+        let mut acked = 0;
+        loop {
+            let fetched = broker.fetch("slow", 60_000, FetchBudget::new(1000, 0)).await.unwrap();
+            if fetched.is_empty() {
+                break;
+            }
+            for msg in &fetched {
+                broker.ack(&msg.id, "slow").await.unwrap();
+            }
+            acked += fetched.len();
+            assert!(broker.topics["t"].msgs.len() <= RESIDENT_WINDOW);
+        }
+        assert_eq!(acked, total);
+        assert!(broker.topics["t"].msgs.is_empty());
+        assert!(broker.msg_index.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_patterns_with_many_multi_level_wildcards() {
+        let mut broker = Broker::new();
+        broker.create_topic("a.b", TopicConfig::default()).await.unwrap();
+        broker.subscribe("#.a.#.b.#.#", "c", "").await.unwrap();
+        let pattern = "#.a.#.b.#.c.#.d.#";
+        assert!(matches!(broker.subscribe(pattern, "c", "").await, Err(BrokerError::InvalidPattern(..))));
+        assert!(!broker.patterns.contains_key(pattern));
+        assert!(broker.topics["a.b"].subscribers.contains_key("c"));
+    }
+
+    #[tokio::test]
+    async fn pattern_unsubscribe_keeps_subscriptions_held_otherwise() {
+        let mut broker = Broker::new();
+        for name in ["orders.eu", "stock.eu", "prices.eu"] {
+            broker.create_topic(name, TopicConfig::default()).await.unwrap();
+        }
+        broker.subscribe("orders.#", "c", "").await.unwrap();
+        broker.subscribe("*.eu", "c", "").await.unwrap();
+        broker.subscribe("stock.eu", "c", "").await.unwrap();
+        broker.unsubscribe("*.eu", "c").await.unwrap();
+
+        let subscribed = |broker: &Broker, name: &str| broker.topics[name].subscribers.contains_key("c");
+        assert!(subscribed(&broker, "orders.eu"));
+        assert!(subscribed(&broker, "stock.eu"));
+        assert!(!subscribed(&broker, "prices.eu"));
+
+        let mut broker = Broker::open(broker.into_storage()).await.unwrap();
+        broker.snapshot().await.unwrap();
+        let mut broker = Broker::open(broker.into_storage()).await.unwrap();
+        broker.unsubscribe("orders.#", "c").await.unwrap();
+        assert!(!subscribed(&broker, "orders.eu"));
+        assert!(subscribed(&broker, "stock.eu"));
+    }
+
+    #[tokio::test]
+    async fn post_stores_a_repeated_post_once() {
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        let mut msg = Msg::new(b"m");
+        msg.idempotency_key = "k".to_string();
+        let first = broker.post("t", msg.clone()).await.unwrap();
+        let retry = broker.post("t", msg).await.unwrap();
+        assert_eq!((first.offset, first.duplicate), (0, false));
+        assert_eq!((retry.offset, retry.duplicate), (0, true));
+        assert_eq!(broker.storage.next_offset("t"), 1);
+    }
+
+    #[tokio::test]
+    async fn batch_duplicates_share_the_outcome_of_the_post_they_repeat() {
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        let keyed = |key: &str| {
+            let mut msg = Msg::new(key.as_bytes());
+            msg.idempotency_key = key.to_string();
+            ("t".to_string(), msg)
+        };
+
+        let results = broker.post_batch(vec![keyed("a"), keyed("b"), keyed("a")]).await;
+        let results: Vec<Posted> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!((results[2].offset, results[2].duplicate), (results[0].offset, true));
+        assert!(!results[0].duplicate && !results[1].duplicate);
+
+        // A failed write leaves nothing for a repeat to point at.
+        broker.storage.delete_topic("t").unwrap();
+        let results = broker.post_batch(vec![keyed("c"), keyed("c")]).await;
+        assert!(results.iter().all(|result| matches!(result, Err(BrokerError::Storage(_)))));
+    }
+
+    #[tokio::test]
+    async fn transaction_stores_a_repeated_post_once() {
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        let transaction_id = broker.begin_transaction().await;
+        for payload in ["first", "retry"] {
+            let mut msg = Msg::new(payload.as_bytes());
+            msg.producer_id = "p".to_string();
+            msg.sequence = 1;
+            broker.post_in_transaction(&transaction_id, "t", msg).await.unwrap();
+        }
+        assert_eq!(broker.commit_transaction(&transaction_id).await.unwrap(), [0, 0]);
+        assert_eq!(broker.storage.next_offset("t"), 1);
+    }
+
+    #[tokio::test]
+    async fn fetch_rotates_across_topics() {
+        let mut broker = Broker::new();
+        for topic_name in ["a", "b", "c"] {
+            broker.create_topic(topic_name, TopicConfig::default()).await.unwrap();
+            broker.subscribe(topic_name, "client", "").await.unwrap();
+            for _ in 0..10 {
+                broker.post(topic_name, Msg::new(topic_name.as_bytes())).await.unwrap();
+            }
+        }
+        let mut fetched = vec![];
+        for _ in 0..3 {
+            fetched.extend(broker.fetch("client", 60_000, FetchBudget::new(2, 0)).await.unwrap());
+        }
+        let mut topic_names: Vec<String> = fetched.iter().map(|msg| broker.msg_index[&msg.id].0.clone()).collect();
+        topic_names.sort();
+        topic_names.dedup();
+        assert_eq!(topic_names, ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn forgets_fetch_turns_of_clients_without_subscriptions() {
+        let mut broker = Broker::new();
+        broker.create_topic("a", TopicConfig::default()).await.unwrap();
+        broker.create_topic("b", TopicConfig::default()).await.unwrap();
+        broker.subscribe("a", "c", "").await.unwrap();
+        broker.subscribe("b", "c", "").await.unwrap();
+        broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        broker.unsubscribe("a", "c").await.unwrap();
+        assert!(broker.fetch_turns.contains_key("c"));
+        broker.unsubscribe("b", "c").await.unwrap();
+        assert!(broker.fetch_turns.is_empty());
+
+        broker.reply_topic("c").await.unwrap();
+        broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        broker.delete_reply_topic("c").await.unwrap();
+        assert!(broker.fetch_turns.is_empty());
+        broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert!(broker.fetch_turns.is_empty());
+    }
+
+    #[tokio::test]
+    async fn leases_hold_msgs_back_until_they_end() {
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        broker.subscribe("t", "c", "").await.unwrap();
+        broker.post("t", Msg::new(b"m")).await.unwrap();
+        let attempts = |fetched: &[Msg]| fetched.iter().map(|msg| msg.attempts).collect::<Vec<_>>();
+
+        let fetched = broker.fetch("c", 50, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(attempts(&fetched), [1]);
+        let msg_id = fetched[0].id.clone();
+        broker.extend_lease(&msg_id, "c", 60_000).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(broker.fetch("c", 50, FetchBudget::new(0, 0)).await.unwrap().is_empty());
+        assert!(matches!(broker.extend_lease(&msg_id, "other", 60_000).await, Err(BrokerError::LeaseNotFound(..))));
+        assert!(matches!(broker.extend_lease("unknown", "c", 60_000).await, Err(BrokerError::MessageNotFound(..))));
+
+        broker.release_leases("c").await;
+        assert_eq!(attempts(&broker.fetch("c", 50, FetchBudget::new(0, 0)).await.unwrap()), [2]);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(matches!(broker.extend_lease(&msg_id, "c", 60_000).await, Err(BrokerError::LeaseNotFound(..))));
+        assert_eq!(attempts(&broker.fetch("c", 50, FetchBudget::new(0, 0)).await.unwrap()), [3]);
+    }
+
+    #[tokio::test]
+    async fn nacked_msgs_come_back_after_their_delay() {
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        // A second subscriber keeps the message around once the first has acked it.
+        for client_id in ["c", "d"] {
+            broker.subscribe("t", client_id, "").await.unwrap();
+        }
+        broker.post("t", Msg::new(b"m")).await.unwrap();
+        let attempts = |fetched: &[Msg]| fetched.iter().map(|msg| msg.attempts).collect::<Vec<_>>();
+
+        let msg_id = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap()[0].id.clone();
+        broker.nack(&msg_id, "c", 50, "busy").await.unwrap();
+        assert!(broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap().is_empty());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(attempts(&broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap()), [2]);
+
+        // A nack without delay makes the message deliverable at once, and survives a release.
+        broker.nack(&msg_id, "c", 0, "retry").await.unwrap();
+        broker.release_leases("c").await;
+        assert_eq!(attempts(&broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap()), [3]);
+        broker.nack(&msg_id, "c", 60_000, "later").await.unwrap();
+        broker.release_leases("c").await;
+        assert!(broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap().is_empty());
+
+        assert!(matches!(broker.nack(&msg_id, "other", 0, "").await, Err(BrokerError::NotPending(..))));
+        broker.ack(&msg_id, "c").await.unwrap();
+        assert!(matches!(broker.nack(&msg_id, "c", 0, "").await, Err(BrokerError::NotPending(..))));
+    }
+
+    #[tokio::test]
+    async fn dead_letters_msgs_out_of_attempts() {
+        let mut broker = Broker::new();
+        let dead_letter = DeadLetterPolicy { topic: "dlq".to_string(), max_attempts: 2 };
+        let config = TopicConfig { dead_letter: Some(dead_letter), ..TopicConfig::default() };
+        broker.create_topic("t", config).await.unwrap();
+        broker.subscribe("t", "c", "").await.unwrap();
+        broker.post("t", Msg::new(b"m")).await.unwrap();
+
+        for attempt in 1..=2 {
+            let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+            assert_eq!(fetched[0].attempts, attempt);
+            broker.nack(&fetched[0].id, "c", 0, &format!("failure {}", attempt)).await.unwrap();
+        }
+        assert!(!broker.topics.contains_key("dlq"));
+        assert!(broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap().is_empty());
+
+        let dead = broker.fetch_from("dlq", 0, 10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].payload, b"m");
+        let origin = dead[0].dead_lettered.as_ref().unwrap();
+        assert_eq!((origin.topic.as_str(), origin.offset, origin.client_id.as_str()), ("t", 0, "c"));
+        assert_eq!((origin.attempts, origin.reason.as_str()), (2, "failure 2"));
+        broker.release_leases("c").await;
+        assert!(broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn scheduled_msgs_wait_for_their_time_across_a_restart() {
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        broker.subscribe("t", "c", "").await.unwrap();
+        let mut scheduled = Msg::new(b"scheduled");
+        scheduled.deliver_at = now_millis() + 500;
+        broker.post("t", scheduled).await.unwrap();
+        broker.post("t", Msg::new(b"now")).await.unwrap();
+
+        let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(payloads(&broker, &fetched, "t"), ["now"]);
+        broker.ack(&fetched[0].id, "c").await.unwrap();
+
+        let mut broker = Broker::open(broker.into_storage()).await.unwrap();
+        assert!(broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap().is_empty());
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(payloads(&broker, &fetched, "t"), ["scheduled"]);
+    }
+
+    #[tokio::test]
+    async fn holds_back_msgs_behind_a_pending_one_with_their_ordering_key() {
+        let mut broker = Broker::new();
+        broker.create_topic("t", TopicConfig::default()).await.unwrap();
+        broker.subscribe("t", "c", "").await.unwrap();
+        for (payload, key) in [("a1", "a"), ("a2", "a"), ("b1", "b"), ("none", "")] {
+            let mut msg = Msg::new(payload.as_bytes());
+            msg.ordering_key = key.to_string();
+            broker.post("t", msg).await.unwrap();
+        }
+
+        let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(payloads(&broker, &fetched, "t"), ["a1", "b1", "none"]);
+        let a1 = fetched.iter().find(|msg| msg.payload == b"a1").unwrap().id.clone();
+
+        // Still held back while the message before it waits out a nack.
+        broker.nack(&a1, "c", 60_000, "").await.unwrap();
+        assert!(broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap().is_empty());
+
+        broker.ack(&a1, "c").await.unwrap();
+        let fetched = broker.fetch("c", 60_000, FetchBudget::new(0, 0)).await.unwrap();
+        assert_eq!(payloads(&broker, &fetched, "t"), ["a2"]);
+    }
+
+    #[tokio::test]
+    async fn restart_with_memory_storage() {
+        check_restart(Box::new(MemoryStorage::default()), |storage| storage).await;
+    }
+
+    #[tokio::test]
+    async fn restart_with_file_storage() {
+        let dir = TempDir::new();
+        check_restart(Box::new(FileStorage::open(dir.path()).unwrap()), |storage| {
+            drop(storage);
+            Box::new(FileStorage::open(dir.path()).unwrap())
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn restart_with_sqlite_storage() {
+        let dir = TempDir::new();
+        check_restart(Box::new(SqliteStorage::open(dir.path()).unwrap()), |storage| {
+            drop(storage);
+            Box::new(SqliteStorage::open(dir.path()).unwrap())
+        })
+        .await;
+    }
+}
@@ -4,6 +4,47 @@
 pub struct CreateTopicRequest {
     #[prost(string, tag = "1")]
     pub name: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub retention: ::core::option::Option<ProtoRetention>,
+    /// Delete messages, from memory and storage, as soon as every subscriber has acked them.
+    #[prost(bool, tag = "3")]
+    pub delete_on_ack: bool,
+    #[prost(message, optional, tag = "4")]
+    pub dead_letter: ::core::option::Option<ProtoDeadLetterPolicy>,
+    /// Topic expired messages not yet acked by every subscriber are moved to; they are
+    /// dropped if empty.
+    #[prost(string, tag = "5")]
+    pub expiry_topic: ::prost::alloc::string::String,
+    /// How long the keys of posted messages are remembered to recognise retried posts; 0
+    /// for the broker default.
+    #[prost(uint64, tag = "6")]
+    pub dedup_window_ms: u64,
+    /// Client the topic is the temporary reply topic of. Only set by the broker, in its journal;
+    /// `CreateTopic` rejects it, as it does names in the reply topic namespace.
+    #[prost(string, tag = "7")]
+    pub reply_owner: ::prost::alloc::string::String,
+}
+/// Once a message has been delivered `max_attempts` times to a subscriber without an ack, it
+/// is moved to the `topic` instead of being delivered to that subscriber again.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoDeadLetterPolicy {
+    #[prost(string, tag = "1")]
+    pub topic: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub max_attempts: u32,
+}
+/// Limits on the messages a topic keeps; 0 means unlimited.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoRetention {
+    #[prost(uint64, tag = "1")]
+    pub max_age_ms: u64,
+    /// Total size of the messages as encoded `ProtoMsg`s, however the storage lays them out.
+    #[prost(uint64, tag = "2")]
+    pub max_bytes: u64,
+    #[prost(uint64, tag = "3")]
+    pub max_msgs: u64,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -11,6 +52,8 @@ pub struct CreateTopicResponse {
     #[prost(string, tag = "1")]
     pub message: ::prost::alloc::string::String,
 }
+/// `topic_name` may be a pattern such as `orders.*.created` or `orders.#`, subscribing the client
+/// to every matching topic, including those created later.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SubscribeRequest {
@@ -18,6 +61,10 @@ pub struct SubscribeRequest {
     pub topic_name: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub client_id: ::prost::alloc::string::String,
+    /// Only messages matching this expression are delivered to the client; the others count as
+    /// acked by it. Subscribing again replaces the filter.
+    #[prost(string, tag = "3")]
+    pub filter: ::prost::alloc::string::String,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -42,31 +89,182 @@ pub struct UnsubscribeResponse {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ProtoMsg {
+    /// Set when the payload is valid UTF-8, for clients exchanging text.
     #[prost(string, tag = "1")]
     pub payload: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub id: ::prost::alloc::string::String,
+    /// Position within the topic, increasing by one with every message posted to it.
+    #[prost(uint64, tag = "3")]
+    pub offset: u64,
+    /// Milliseconds since the Unix epoch at which the message was posted.
+    #[prost(uint64, tag = "4")]
+    pub timestamp: u64,
+    /// Number of times the message has been delivered to the fetching client, this time
+    /// included. Only set on fetch.
+    #[prost(uint32, tag = "5")]
+    pub attempts: u32,
+    /// Set on messages moved to a dead-letter topic.
+    #[prost(message, optional, tag = "6")]
+    pub dead_lettered: ::core::option::Option<ProtoDeadLettered>,
+    /// Milliseconds since the Unix epoch before which the message is not delivered; 0 if it
+    /// was due right away.
+    #[prost(uint64, tag = "7")]
+    pub deliver_at: u64,
+    /// Milliseconds since the Unix epoch from which on the message is no longer delivered;
+    /// 0 if it never expires.
+    #[prost(uint64, tag = "8")]
+    pub expires_at: u64,
+    /// Set on messages moved to an expiry topic.
+    #[prost(message, optional, tag = "9")]
+    pub expired: ::core::option::Option<ProtoExpired>,
+    /// Attributes given by the producer, such as a content type or trace id.
+    #[prost(map = "string, string", tag = "10")]
+    pub headers: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+    /// The payload as raw bytes. Always set on fetch; stored only for payloads that are not
+    /// valid UTF-8.
+    #[prost(bytes = "vec", tag = "11")]
+    pub body: ::prost::alloc::vec::Vec<u8>,
+    #[prost(string, tag = "12")]
+    pub producer_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "13")]
+    pub sequence: u64,
+    #[prost(string, tag = "14")]
+    pub idempotency_key: ::prost::alloc::string::String,
+    #[prost(string, tag = "15")]
+    pub ordering_key: ::prost::alloc::string::String,
+    #[prost(string, tag = "16")]
+    pub reply_to: ::prost::alloc::string::String,
+    #[prost(string, tag = "17")]
+    pub correlation_id: ::prost::alloc::string::String,
+}
+/// Where an expired message came from.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoExpired {
+    #[prost(string, tag = "1")]
+    pub topic: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    #[prost(string, tag = "3")]
+    pub msg_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub expires_at: u64,
+}
+/// Where a dead-lettered message came from and why it was given up on.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoDeadLettered {
+    #[prost(string, tag = "1")]
+    pub topic: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    #[prost(string, tag = "3")]
+    pub msg_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "5")]
+    pub attempts: u32,
+    /// Reason given with the latest nack, if any.
+    #[prost(string, tag = "6")]
+    pub reason: ::prost::alloc::string::String,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ProtoTopic {
     #[prost(string, tag = "1")]
     pub name: ::prost::alloc::string::String,
+    /// Only read from snapshots taken before subscribers had cursors.
     #[prost(string, repeated, tag = "2")]
     pub subscribers: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
     #[prost(message, repeated, tag = "3")]
     pub msgs: ::prost::alloc::vec::Vec<ProtoMsg>,
+    #[prost(uint64, tag = "4")]
+    pub resident_from: u64,
+    #[prost(message, optional, tag = "5")]
+    pub retention: ::core::option::Option<ProtoRetention>,
+    #[prost(uint64, tag = "6")]
+    pub start_offset: u64,
+    #[prost(bool, tag = "7")]
+    pub delete_on_ack: bool,
+    /// Messages deleted after a full ack while an earlier message is still pending.
+    #[prost(uint64, repeated, tag = "8")]
+    pub deleted_offsets: ::prost::alloc::vec::Vec<u64>,
+    #[prost(map = "string, message", tag = "9")]
+    pub cursors: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ProtoCursor,
+    >,
+    #[prost(message, optional, tag = "10")]
+    pub dead_letter: ::core::option::Option<ProtoDeadLetterPolicy>,
+    #[prost(string, tag = "11")]
+    pub expiry_topic: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "12")]
+    pub dedup_window_ms: u64,
+    #[prost(message, repeated, tag = "13")]
+    pub dedup: ::prost::alloc::vec::Vec<ProtoDedupEntry>,
+    #[prost(string, tag = "14")]
+    pub reply_owner: ::prost::alloc::string::String,
+}
+/// Key of a recently posted message, remembered to recognise retried posts.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoDedupEntry {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    #[prost(uint64, tag = "3")]
+    pub timestamp: u64,
+}
+/// Every offset before `next` is acked, and so are the offsets in `acked`.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoCursor {
+    #[prost(uint64, tag = "1")]
+    pub next: u64,
+    #[prost(uint64, repeated, tag = "2")]
+    pub acked: ::prost::alloc::vec::Vec<u64>,
+    #[prost(string, tag = "3")]
+    pub filter: ::prost::alloc::string::String,
+    /// Whether the client subscribed by the topic's name, not only through wildcard patterns.
+    #[prost(bool, tag = "4")]
+    pub direct: bool,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ProtoBroker {
     #[prost(map = "string, message", tag = "1")]
     pub topics: ::std::collections::HashMap<::prost::alloc::string::String, ProtoTopic>,
+    /// Only read from snapshots taken before subscribers had cursors.
     #[prost(map = "string, message", tag = "2")]
     pub acked_msgs: ::std::collections::HashMap<
         ::prost::alloc::string::String,
         ProtoAckedMsgs,
     >,
+    #[prost(uint64, tag = "3")]
+    pub journal_seq: u64,
+    /// Clients subscribed to each wildcard pattern.
+    #[prost(map = "string, message", tag = "4")]
+    pub patterns: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ProtoPatternSubscribers,
+    >,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoPatternSubscribers {
+    #[prost(string, repeated, tag = "1")]
+    pub client_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// Filters of the clients subscribed with one.
+    #[prost(map = "string, string", tag = "2")]
+    pub filters: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -76,23 +274,236 @@ pub struct ProtoAckedMsgs {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoJournalEntry {
+    #[prost(uint64, tag = "1")]
+    pub seq: u64,
+    #[prost(oneof = "proto_journal_entry::Op", tags = "2, 3, 4, 7, 8, 9, 11, 12")]
+    pub op: ::core::option::Option<proto_journal_entry::Op>,
+}
+/// Nested message and enum types in `ProtoJournalEntry`.
+pub mod proto_journal_entry {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Op {
+        #[prost(message, tag = "2")]
+        CreateTopic(super::CreateTopicRequest),
+        #[prost(message, tag = "3")]
+        Subscribe(super::SubscribeRequest),
+        #[prost(message, tag = "4")]
+        Unsubscribe(super::UnsubscribeRequest),
+        #[prost(message, tag = "7")]
+        Truncate(super::ProtoTruncate),
+        #[prost(message, tag = "8")]
+        Expire(super::ProtoExpire),
+        #[prost(message, tag = "9")]
+        Commit(super::ProtoCommit),
+        #[prost(message, tag = "11")]
+        DeleteTopic(super::ProtoDeleteTopic),
+        #[prost(message, tag = "12")]
+        Acks(super::ProtoAcks),
+    }
+}
+/// Messages of a topic before `start_offset` dropped by retention.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoTruncate {
+    #[prost(string, tag = "1")]
+    pub topic_name: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub start_offset: u64,
+}
+/// Acks resolved to the offsets of their messages, so that replaying them does not need the
+/// messages to be in memory.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoAcks {
+    #[prost(message, repeated, tag = "1")]
+    pub acks: ::prost::alloc::vec::Vec<ProtoAck>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoAck {
+    #[prost(string, tag = "1")]
+    pub topic_name: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    #[prost(string, tag = "3")]
+    pub client_id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoDeleteTopic {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+}
+/// A committed transaction, applied as a whole.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoCommit {
+    #[prost(message, repeated, tag = "1")]
+    pub msgs: ::prost::alloc::vec::Vec<ProtoCommittedMsg>,
+    #[prost(message, repeated, tag = "2")]
+    pub acks: ::prost::alloc::vec::Vec<ProtoAck>,
+}
+/// A message posted by a transaction, with the offset it is stored at.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoCommittedMsg {
+    #[prost(string, tag = "1")]
+    pub topic_name: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub msg: ::core::option::Option<ProtoMsg>,
+}
+/// Messages of a topic dropped once their time to live had passed.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoExpire {
+    #[prost(string, tag = "1")]
+    pub topic_name: ::prost::alloc::string::String,
+    #[prost(uint64, repeated, tag = "2")]
+    pub offsets: ::prost::alloc::vec::Vec<u64>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PostRequest {
     #[prost(string, tag = "1")]
     pub topic_name: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub payload: ::prost::alloc::string::String,
+    /// Milliseconds since the Unix epoch before which the message is not delivered. Takes
+    /// precedence over `delay_ms`.
+    #[prost(uint64, tag = "3")]
+    pub deliver_at: u64,
+    /// Milliseconds from now before which the message is not delivered.
+    #[prost(uint64, tag = "4")]
+    pub delay_ms: u64,
+    /// Milliseconds after which the message expires if not consumed; 0 to keep it.
+    #[prost(uint64, tag = "5")]
+    pub ttl_ms: u64,
+    #[prost(map = "string, string", tag = "6")]
+    pub headers: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+    /// Binary payload, set instead of `payload`.
+    #[prost(bytes = "vec", tag = "7")]
+    pub body: ::prost::alloc::vec::Vec<u8>,
+    /// A post repeating the `producer_id` and `sequence`, or the `idempotency_key`, of a
+    /// message posted to the topic within its dedup window is not stored again.
+    #[prost(string, tag = "8")]
+    pub producer_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "9")]
+    pub sequence: u64,
+    #[prost(string, tag = "10")]
+    pub idempotency_key: ::prost::alloc::string::String,
+    /// Post as part of the transaction instead of right away.
+    #[prost(string, tag = "11")]
+    pub transaction_id: ::prost::alloc::string::String,
+    /// Messages with the same key are delivered to each subscriber in order: a message is not
+    /// delivered before the earlier ones with its key have been acked.
+    #[prost(string, tag = "12")]
+    pub ordering_key: ::prost::alloc::string::String,
+    /// Topic replies to the message should be posted to, with its `correlation_id`.
+    #[prost(string, tag = "13")]
+    pub reply_to: ::prost::alloc::string::String,
+    #[prost(string, tag = "14")]
+    pub correlation_id: ::prost::alloc::string::String,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PostResponse {
     #[prost(string, tag = "1")]
     pub message: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    /// Set when the message had been posted before; `offset` is that of the original.
+    #[prost(bool, tag = "3")]
+    pub duplicate: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PostBatchRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub posts: ::prost::alloc::vec::Vec<PostRequest>,
+}
+/// One result per post, in the order of the request.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PostBatchResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub results: ::prost::alloc::vec::Vec<PostResult>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PostResult {
+    #[prost(uint64, tag = "1")]
+    pub offset: u64,
+    #[prost(bool, tag = "2")]
+    pub duplicate: bool,
+    /// Why the post failed; empty if it succeeded.
+    #[prost(string, tag = "3")]
+    pub error: ::prost::alloc::string::String,
 }
+/// Fetched messages are leased to the client: they are not delivered to it again until the
+/// lease expires without an ack.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FetchRequest {
     #[prost(string, tag = "1")]
     pub client_id: ::prost::alloc::string::String,
+    /// 0 uses the broker's default lease.
+    #[prost(uint64, tag = "2")]
+    pub lease_ms: u64,
+    /// Limits on the messages returned, 0 for no limit. The payload size of the first message
+    /// is not limited, so that a large message can be fetched at all.
+    #[prost(uint32, tag = "3")]
+    pub max_msgs: u32,
+    #[prost(uint64, tag = "4")]
+    pub max_bytes: u64,
+    /// How long to wait for messages if none are available right away; 0 to return at once.
+    #[prost(uint64, tag = "5")]
+    pub wait_ms: u64,
+}
+/// Keeps pushing the messages of the client's subscriptions as they become available, leased
+/// like fetched ones. Leases the client still holds are released first, so that a reconnecting
+/// client resumes after the messages it has acked.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StreamRequest {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    /// 0 uses the broker's default lease.
+    #[prost(uint64, tag = "2")]
+    pub lease_ms: u64,
+}
+/// Sent by the client of a `Consume` stream. The first request names the client and, like
+/// opening a `Stream`, releases its leases. Every request may grant credits, each allowing the
+/// broker to push one more message, and ack messages pushed before.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ConsumeRequest {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    /// 0 uses the broker's default lease. Only read from the first request.
+    #[prost(uint64, tag = "2")]
+    pub lease_ms: u64,
+    #[prost(uint32, tag = "3")]
+    pub credits: u32,
+    /// Ids of the messages to ack.
+    #[prost(string, repeated, tag = "4")]
+    pub acks: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Reads the messages of a topic in offset order, acked or not, without subscribing to it.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FetchFromRequest {
+    #[prost(string, tag = "1")]
+    pub topic_name: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    /// At most this many messages are returned; 0 means a default of 100.
+    #[prost(uint32, tag = "3")]
+    pub max_msgs: u32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -107,6 +518,9 @@ pub struct AckRequest {
     pub msg_id: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub client_id: ::prost::alloc::string::String,
+    /// Ack as part of the transaction instead of right away.
+    #[prost(string, tag = "3")]
+    pub transaction_id: ::prost::alloc::string::String,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -114,6 +528,137 @@ pub struct AckResponse {
     #[prost(string, tag = "1")]
     pub message: ::prost::alloc::string::String,
 }
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AckBatchRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub acks: ::prost::alloc::vec::Vec<AckRequest>,
+}
+/// One result per ack, in the order of the request.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AckBatchResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub results: ::prost::alloc::vec::Vec<AckResult>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AckResult {
+    /// Why the ack failed; empty if it succeeded.
+    #[prost(string, tag = "1")]
+    pub error: ::prost::alloc::string::String,
+}
+/// Restarts the lease of a message fetched by the client, from now on.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExtendLeaseRequest {
+    #[prost(string, tag = "1")]
+    pub msg_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub client_id: ::prost::alloc::string::String,
+    /// 0 uses the broker's default lease.
+    #[prost(uint64, tag = "3")]
+    pub lease_ms: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExtendLeaseResponse {
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
+}
+/// Gives up a message the client failed to process, to be delivered to it again once
+/// `delay_ms` has passed.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NackRequest {
+    #[prost(string, tag = "1")]
+    pub msg_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub delay_ms: u64,
+    /// Why the message could not be processed, passed on if it ends up dead-lettered.
+    #[prost(string, tag = "4")]
+    pub reason: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NackResponse {
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
+}
+/// Starts a transaction, to which posts and acks are added by passing its id. None of them
+/// take effect until it is committed.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BeginTransactionRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BeginTransactionResponse {
+    #[prost(string, tag = "1")]
+    pub transaction_id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CommitTransactionRequest {
+    #[prost(string, tag = "1")]
+    pub transaction_id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CommitTransactionResponse {
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
+    /// Offsets of the posted messages, in the order they were posted.
+    #[prost(uint64, repeated, tag = "2")]
+    pub offsets: ::prost::alloc::vec::Vec<u64>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AbortTransactionRequest {
+    #[prost(string, tag = "1")]
+    pub transaction_id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AbortTransactionResponse {
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
+}
+/// Returns the temporary reply topic of the client, creating it and subscribing the client to it
+/// if needed. The topic is deleted when a `Stream` or `Consume` of the client ends, or once it
+/// has been neither posted to nor fetched from by the client for a while.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateReplyTopicRequest {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateReplyTopicResponse {
+    #[prost(string, tag = "1")]
+    pub topic_name: ::prost::alloc::string::String,
+}
+/// Posts `post` with the client's reply topic as `reply_to`, and a fresh correlation id unless
+/// it has one, then waits for the reply carrying that correlation id.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PostAndWaitRequest {
+    #[prost(message, optional, tag = "1")]
+    pub post: ::core::option::Option<PostRequest>,
+    #[prost(string, tag = "2")]
+    pub client_id: ::prost::alloc::string::String,
+    /// 0 waits for the broker default of 30 seconds.
+    #[prost(uint64, tag = "3")]
+    pub timeout_ms: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PostAndWaitResponse {
+    #[prost(message, optional, tag = "1")]
+    pub reply: ::core::option::Option<ProtoMsg>,
+}
 /// Generated client implementations.
 pub mod broker_service_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -296,6 +841,31 @@ pub mod broker_service_client {
                 .insert(GrpcMethod::new("broker_service.BrokerService", "Post"));
             self.inner.unary(req, path, codec).await
         }
+        pub async fn post_batch(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PostBatchRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PostBatchResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/PostBatch",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("broker_service.BrokerService", "PostBatch"));
+            self.inner.unary(req, path, codec).await
+        }
         pub async fn fetch(
             &mut self,
             request: impl tonic::IntoRequest<super::FetchRequest>,
@@ -318,10 +888,10 @@ pub mod broker_service_client {
                 .insert(GrpcMethod::new("broker_service.BrokerService", "Fetch"));
             self.inner.unary(req, path, codec).await
         }
-        pub async fn ack(
+        pub async fn fetch_from(
             &mut self,
-            request: impl tonic::IntoRequest<super::AckRequest>,
-        ) -> std::result::Result<tonic::Response<super::AckResponse>, tonic::Status> {
+            request: impl tonic::IntoRequest<super::FetchFromRequest>,
+        ) -> std::result::Result<tonic::Response<super::FetchResponse>, tonic::Status> {
             self.inner
                 .ready()
                 .await
@@ -333,78 +903,439 @@ pub mod broker_service_client {
                 })?;
             let codec = tonic::codec::ProstCodec::default();
             let path = http::uri::PathAndQuery::from_static(
-                "/broker_service.BrokerService/Ack",
+                "/broker_service.BrokerService/FetchFrom",
             );
             let mut req = request.into_request();
             req.extensions_mut()
-                .insert(GrpcMethod::new("broker_service.BrokerService", "Ack"));
+                .insert(GrpcMethod::new("broker_service.BrokerService", "FetchFrom"));
             self.inner.unary(req, path, codec).await
         }
-    }
-}
-/// Generated server implementations.
-pub mod broker_service_server {
-    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
-    use tonic::codegen::*;
-    /// Generated trait containing gRPC methods that should be implemented for use with BrokerServiceServer.
-    #[async_trait]
-    pub trait BrokerService: Send + Sync + 'static {
-        async fn create_topic(
-            &self,
-            request: tonic::Request<super::CreateTopicRequest>,
-        ) -> std::result::Result<
-            tonic::Response<super::CreateTopicResponse>,
-            tonic::Status,
-        >;
-        async fn subscribe(
-            &self,
-            request: tonic::Request<super::SubscribeRequest>,
+        pub async fn stream(
+            &mut self,
+            request: impl tonic::IntoRequest<super::StreamRequest>,
         ) -> std::result::Result<
-            tonic::Response<super::SubscribeResponse>,
+            tonic::Response<tonic::codec::Streaming<super::ProtoMsg>>,
             tonic::Status,
-        >;
-        async fn unsubscribe(
-            &self,
-            request: tonic::Request<super::UnsubscribeRequest>,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/Stream",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("broker_service.BrokerService", "Stream"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        pub async fn consume(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::ConsumeRequest>,
         ) -> std::result::Result<
-            tonic::Response<super::UnsubscribeResponse>,
+            tonic::Response<tonic::codec::Streaming<super::ProtoMsg>>,
             tonic::Status,
-        >;
-        async fn post(
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/Consume",
+            );
+            let mut req = request.into_streaming_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("broker_service.BrokerService", "Consume"));
+            self.inner.streaming(req, path, codec).await
+        }
+        pub async fn ack(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AckRequest>,
+        ) -> std::result::Result<tonic::Response<super::AckResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/Ack",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("broker_service.BrokerService", "Ack"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn ack_batch(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AckBatchRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::AckBatchResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/AckBatch",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("broker_service.BrokerService", "AckBatch"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn extend_lease(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ExtendLeaseRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ExtendLeaseResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/ExtendLease",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("broker_service.BrokerService", "ExtendLease"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn nack(
+            &mut self,
+            request: impl tonic::IntoRequest<super::NackRequest>,
+        ) -> std::result::Result<tonic::Response<super::NackResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/Nack",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("broker_service.BrokerService", "Nack"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn begin_transaction(
+            &mut self,
+            request: impl tonic::IntoRequest<super::BeginTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::BeginTransactionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/BeginTransaction",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("broker_service.BrokerService", "BeginTransaction"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn commit_transaction(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CommitTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CommitTransactionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/CommitTransaction",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("broker_service.BrokerService", "CommitTransaction"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn abort_transaction(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AbortTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::AbortTransactionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/AbortTransaction",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("broker_service.BrokerService", "AbortTransaction"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn create_reply_topic(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CreateReplyTopicRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CreateReplyTopicResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/CreateReplyTopic",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("broker_service.BrokerService", "CreateReplyTopic"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn post_and_wait(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PostAndWaitRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PostAndWaitResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/broker_service.BrokerService/PostAndWait",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("broker_service.BrokerService", "PostAndWait"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod broker_service_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with BrokerServiceServer.
+    #[async_trait]
+    pub trait BrokerService: Send + Sync + 'static {
+        async fn create_topic(
+            &self,
+            request: tonic::Request<super::CreateTopicRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CreateTopicResponse>,
+            tonic::Status,
+        >;
+        async fn subscribe(
+            &self,
+            request: tonic::Request<super::SubscribeRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SubscribeResponse>,
+            tonic::Status,
+        >;
+        async fn unsubscribe(
+            &self,
+            request: tonic::Request<super::UnsubscribeRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::UnsubscribeResponse>,
+            tonic::Status,
+        >;
+        async fn post(
             &self,
             request: tonic::Request<super::PostRequest>,
         ) -> std::result::Result<tonic::Response<super::PostResponse>, tonic::Status>;
+        async fn post_batch(
+            &self,
+            request: tonic::Request<super::PostBatchRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PostBatchResponse>,
+            tonic::Status,
+        >;
         async fn fetch(
             &self,
             request: tonic::Request<super::FetchRequest>,
         ) -> std::result::Result<tonic::Response<super::FetchResponse>, tonic::Status>;
+        async fn fetch_from(
+            &self,
+            request: tonic::Request<super::FetchFromRequest>,
+        ) -> std::result::Result<tonic::Response<super::FetchResponse>, tonic::Status>;
+        /// Server streaming response type for the Stream method.
+        type StreamStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::ProtoMsg, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        async fn stream(
+            &self,
+            request: tonic::Request<super::StreamRequest>,
+        ) -> std::result::Result<tonic::Response<Self::StreamStream>, tonic::Status>;
+        /// Server streaming response type for the Consume method.
+        type ConsumeStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::ProtoMsg, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        async fn consume(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::ConsumeRequest>>,
+        ) -> std::result::Result<tonic::Response<Self::ConsumeStream>, tonic::Status>;
         async fn ack(
             &self,
             request: tonic::Request<super::AckRequest>,
         ) -> std::result::Result<tonic::Response<super::AckResponse>, tonic::Status>;
-    }
-    #[derive(Debug)]
-    pub struct BrokerServiceServer<T: BrokerService> {
-        inner: _Inner<T>,
-        accept_compression_encodings: EnabledCompressionEncodings,
-        send_compression_encodings: EnabledCompressionEncodings,
-        max_decoding_message_size: Option<usize>,
-        max_encoding_message_size: Option<usize>,
-    }
-    struct _Inner<T>(Arc<T>);
-    impl<T: BrokerService> BrokerServiceServer<T> {
-        pub fn new(inner: T) -> Self {
-            Self::from_arc(Arc::new(inner))
-        }
-        pub fn from_arc(inner: Arc<T>) -> Self {
-            let inner = _Inner(inner);
-            Self {
-                inner,
-                accept_compression_encodings: Default::default(),
-                send_compression_encodings: Default::default(),
-                max_decoding_message_size: None,
-                max_encoding_message_size: None,
-            }
+        async fn ack_batch(
+            &self,
+            request: tonic::Request<super::AckBatchRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::AckBatchResponse>,
+            tonic::Status,
+        >;
+        async fn extend_lease(
+            &self,
+            request: tonic::Request<super::ExtendLeaseRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ExtendLeaseResponse>,
+            tonic::Status,
+        >;
+        async fn nack(
+            &self,
+            request: tonic::Request<super::NackRequest>,
+        ) -> std::result::Result<tonic::Response<super::NackResponse>, tonic::Status>;
+        async fn begin_transaction(
+            &self,
+            request: tonic::Request<super::BeginTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::BeginTransactionResponse>,
+            tonic::Status,
+        >;
+        async fn commit_transaction(
+            &self,
+            request: tonic::Request<super::CommitTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CommitTransactionResponse>,
+            tonic::Status,
+        >;
+        async fn abort_transaction(
+            &self,
+            request: tonic::Request<super::AbortTransactionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::AbortTransactionResponse>,
+            tonic::Status,
+        >;
+        async fn create_reply_topic(
+            &self,
+            request: tonic::Request<super::CreateReplyTopicRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CreateReplyTopicResponse>,
+            tonic::Status,
+        >;
+        async fn post_and_wait(
+            &self,
+            request: tonic::Request<super::PostAndWaitRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PostAndWaitResponse>,
+            tonic::Status,
+        >;
+    }
+    #[derive(Debug)]
+    pub struct BrokerServiceServer<T: BrokerService> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: BrokerService> BrokerServiceServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
         }
         pub fn with_interceptor<F>(
             inner: T,
@@ -464,23 +1395,437 @@ pub mod broker_service_server {
             match req.uri().path() {
                 "/broker_service.BrokerService/CreateTopic" => {
                     #[allow(non_camel_case_types)]
-                    struct CreateTopicSvc<T: BrokerService>(pub Arc<T>);
+                    struct CreateTopicSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::CreateTopicRequest>
+                    for CreateTopicSvc<T> {
+                        type Response = super::CreateTopicResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateTopicRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::create_topic(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = CreateTopicSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/Subscribe" => {
+                    #[allow(non_camel_case_types)]
+                    struct SubscribeSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::SubscribeRequest>
+                    for SubscribeSvc<T> {
+                        type Response = super::SubscribeResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubscribeRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::subscribe(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SubscribeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/Unsubscribe" => {
+                    #[allow(non_camel_case_types)]
+                    struct UnsubscribeSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::UnsubscribeRequest>
+                    for UnsubscribeSvc<T> {
+                        type Response = super::UnsubscribeResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::UnsubscribeRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::unsubscribe(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = UnsubscribeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/Post" => {
+                    #[allow(non_camel_case_types)]
+                    struct PostSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::PostRequest> for PostSvc<T> {
+                        type Response = super::PostResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PostRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::post(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PostSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/PostBatch" => {
+                    #[allow(non_camel_case_types)]
+                    struct PostBatchSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::PostBatchRequest>
+                    for PostBatchSvc<T> {
+                        type Response = super::PostBatchResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PostBatchRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::post_batch(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PostBatchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/Fetch" => {
+                    #[allow(non_camel_case_types)]
+                    struct FetchSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::FetchRequest> for FetchSvc<T> {
+                        type Response = super::FetchResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::FetchRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::fetch(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = FetchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/FetchFrom" => {
+                    #[allow(non_camel_case_types)]
+                    struct FetchFromSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::FetchFromRequest>
+                    for FetchFromSvc<T> {
+                        type Response = super::FetchResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::FetchFromRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::fetch_from(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = FetchFromSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/Stream" => {
+                    #[allow(non_camel_case_types)]
+                    struct StreamSvc<T: BrokerService>(pub Arc<T>);
                     impl<
                         T: BrokerService,
-                    > tonic::server::UnaryService<super::CreateTopicRequest>
-                    for CreateTopicSvc<T> {
-                        type Response = super::CreateTopicResponse;
+                    > tonic::server::ServerStreamingService<super::StreamRequest>
+                    for StreamSvc<T> {
+                        type Response = super::ProtoMsg;
+                        type ResponseStream = T::StreamStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::StreamRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::stream(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = StreamSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/Consume" => {
+                    #[allow(non_camel_case_types)]
+                    struct ConsumeSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::StreamingService<super::ConsumeRequest>
+                    for ConsumeSvc<T> {
+                        type Response = super::ProtoMsg;
+                        type ResponseStream = T::ConsumeStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                tonic::Streaming<super::ConsumeRequest>,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::consume(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ConsumeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/Ack" => {
+                    #[allow(non_camel_case_types)]
+                    struct AckSvc<T: BrokerService>(pub Arc<T>);
+                    impl<T: BrokerService> tonic::server::UnaryService<super::AckRequest>
+                    for AckSvc<T> {
+                        type Response = super::AckResponse;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::CreateTopicRequest>,
+                            request: tonic::Request<super::AckRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as BrokerService>::create_topic(&inner, request).await
+                                <T as BrokerService>::ack(&inner, request).await
                             };
                             Box::pin(fut)
                         }
@@ -492,7 +1837,7 @@ pub mod broker_service_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = CreateTopicSvc(inner);
+                        let method = AckSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -508,25 +1853,25 @@ pub mod broker_service_server {
                     };
                     Box::pin(fut)
                 }
-                "/broker_service.BrokerService/Subscribe" => {
+                "/broker_service.BrokerService/AckBatch" => {
                     #[allow(non_camel_case_types)]
-                    struct SubscribeSvc<T: BrokerService>(pub Arc<T>);
+                    struct AckBatchSvc<T: BrokerService>(pub Arc<T>);
                     impl<
                         T: BrokerService,
-                    > tonic::server::UnaryService<super::SubscribeRequest>
-                    for SubscribeSvc<T> {
-                        type Response = super::SubscribeResponse;
+                    > tonic::server::UnaryService<super::AckBatchRequest>
+                    for AckBatchSvc<T> {
+                        type Response = super::AckBatchResponse;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::SubscribeRequest>,
+                            request: tonic::Request<super::AckBatchRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as BrokerService>::subscribe(&inner, request).await
+                                <T as BrokerService>::ack_batch(&inner, request).await
                             };
                             Box::pin(fut)
                         }
@@ -538,7 +1883,7 @@ pub mod broker_service_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = SubscribeSvc(inner);
+                        let method = AckBatchSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -554,25 +1899,25 @@ pub mod broker_service_server {
                     };
                     Box::pin(fut)
                 }
-                "/broker_service.BrokerService/Unsubscribe" => {
+                "/broker_service.BrokerService/ExtendLease" => {
                     #[allow(non_camel_case_types)]
-                    struct UnsubscribeSvc<T: BrokerService>(pub Arc<T>);
+                    struct ExtendLeaseSvc<T: BrokerService>(pub Arc<T>);
                     impl<
                         T: BrokerService,
-                    > tonic::server::UnaryService<super::UnsubscribeRequest>
-                    for UnsubscribeSvc<T> {
-                        type Response = super::UnsubscribeResponse;
+                    > tonic::server::UnaryService<super::ExtendLeaseRequest>
+                    for ExtendLeaseSvc<T> {
+                        type Response = super::ExtendLeaseResponse;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::UnsubscribeRequest>,
+                            request: tonic::Request<super::ExtendLeaseRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as BrokerService>::unsubscribe(&inner, request).await
+                                <T as BrokerService>::extend_lease(&inner, request).await
                             };
                             Box::pin(fut)
                         }
@@ -584,7 +1929,7 @@ pub mod broker_service_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = UnsubscribeSvc(inner);
+                        let method = ExtendLeaseSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -600,24 +1945,24 @@ pub mod broker_service_server {
                     };
                     Box::pin(fut)
                 }
-                "/broker_service.BrokerService/Post" => {
+                "/broker_service.BrokerService/Nack" => {
                     #[allow(non_camel_case_types)]
-                    struct PostSvc<T: BrokerService>(pub Arc<T>);
+                    struct NackSvc<T: BrokerService>(pub Arc<T>);
                     impl<
                         T: BrokerService,
-                    > tonic::server::UnaryService<super::PostRequest> for PostSvc<T> {
-                        type Response = super::PostResponse;
+                    > tonic::server::UnaryService<super::NackRequest> for NackSvc<T> {
+                        type Response = super::NackResponse;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::PostRequest>,
+                            request: tonic::Request<super::NackRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as BrokerService>::post(&inner, request).await
+                                <T as BrokerService>::nack(&inner, request).await
                             };
                             Box::pin(fut)
                         }
@@ -629,7 +1974,7 @@ pub mod broker_service_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = PostSvc(inner);
+                        let method = NackSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -645,24 +1990,26 @@ pub mod broker_service_server {
                     };
                     Box::pin(fut)
                 }
-                "/broker_service.BrokerService/Fetch" => {
+                "/broker_service.BrokerService/BeginTransaction" => {
                     #[allow(non_camel_case_types)]
-                    struct FetchSvc<T: BrokerService>(pub Arc<T>);
+                    struct BeginTransactionSvc<T: BrokerService>(pub Arc<T>);
                     impl<
                         T: BrokerService,
-                    > tonic::server::UnaryService<super::FetchRequest> for FetchSvc<T> {
-                        type Response = super::FetchResponse;
+                    > tonic::server::UnaryService<super::BeginTransactionRequest>
+                    for BeginTransactionSvc<T> {
+                        type Response = super::BeginTransactionResponse;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::FetchRequest>,
+                            request: tonic::Request<super::BeginTransactionRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as BrokerService>::fetch(&inner, request).await
+                                <T as BrokerService>::begin_transaction(&inner, request)
+                                    .await
                             };
                             Box::pin(fut)
                         }
@@ -674,7 +2021,7 @@ pub mod broker_service_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = FetchSvc(inner);
+                        let method = BeginTransactionSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -690,23 +2037,26 @@ pub mod broker_service_server {
                     };
                     Box::pin(fut)
                 }
-                "/broker_service.BrokerService/Ack" => {
+                "/broker_service.BrokerService/CommitTransaction" => {
                     #[allow(non_camel_case_types)]
-                    struct AckSvc<T: BrokerService>(pub Arc<T>);
-                    impl<T: BrokerService> tonic::server::UnaryService<super::AckRequest>
-                    for AckSvc<T> {
-                        type Response = super::AckResponse;
+                    struct CommitTransactionSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::CommitTransactionRequest>
+                    for CommitTransactionSvc<T> {
+                        type Response = super::CommitTransactionResponse;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
                             tonic::Status,
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::AckRequest>,
+                            request: tonic::Request<super::CommitTransactionRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as BrokerService>::ack(&inner, request).await
+                                <T as BrokerService>::commit_transaction(&inner, request)
+                                    .await
                             };
                             Box::pin(fut)
                         }
@@ -718,7 +2068,147 @@ pub mod broker_service_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = AckSvc(inner);
+                        let method = CommitTransactionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/AbortTransaction" => {
+                    #[allow(non_camel_case_types)]
+                    struct AbortTransactionSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::AbortTransactionRequest>
+                    for AbortTransactionSvc<T> {
+                        type Response = super::AbortTransactionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AbortTransactionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::abort_transaction(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = AbortTransactionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/CreateReplyTopic" => {
+                    #[allow(non_camel_case_types)]
+                    struct CreateReplyTopicSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::CreateReplyTopicRequest>
+                    for CreateReplyTopicSvc<T> {
+                        type Response = super::CreateReplyTopicResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateReplyTopicRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::create_reply_topic(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = CreateReplyTopicSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/broker_service.BrokerService/PostAndWait" => {
+                    #[allow(non_camel_case_types)]
+                    struct PostAndWaitSvc<T: BrokerService>(pub Arc<T>);
+                    impl<
+                        T: BrokerService,
+                    > tonic::server::UnaryService<super::PostAndWaitRequest>
+                    for PostAndWaitSvc<T> {
+                        type Response = super::PostAndWaitResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PostAndWaitRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as BrokerService>::post_and_wait(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PostAndWaitSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -0,0 +1,50 @@
+use std::env;
+
+const DEFAULT_DATA_DIR: &str = ".";
+const DEFAULT_LEASE_MS: u64 = 30_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    Memory,
+    File,
+    Sqlite,
+}
+
+/// Broker settings, read from `SPIPES_*` environment variables.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `SPIPES_STORAGE`: `memory`, `file` (default) or `sqlite`.
+    pub storage: StorageKind,
+    /// `SPIPES_DATA_DIR`: where the file and sqlite backends keep their data.
+    pub data_dir: String,
+    /// `SPIPES_LEASE_MS`: how long a fetched message stays invisible to the client that
+    /// fetched it, unless the fetch asks for another duration.
+    pub lease_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            storage: StorageKind::File,
+            data_dir: DEFAULT_DATA_DIR.to_string(),
+            lease_ms: DEFAULT_LEASE_MS,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let storage = match env::var("SPIPES_STORAGE").as_deref() {
+            Err(_) | Ok("file") => StorageKind::File,
+            Ok("memory") => StorageKind::Memory,
+            Ok("sqlite") => StorageKind::Sqlite,
+            Ok(other) => return Err(format!("unknown SPIPES_STORAGE '{}'", other).into()),
+        };
+        let data_dir = env::var("SPIPES_DATA_DIR").unwrap_or_else(|_| DEFAULT_DATA_DIR.to_string());
+        let lease_ms = match env::var("SPIPES_LEASE_MS") {
+            Ok(lease_ms) => lease_ms.parse().map_err(|e| format!("invalid SPIPES_LEASE_MS '{}': {}", lease_ms, e))?,
+            Err(_) => DEFAULT_LEASE_MS,
+        };
+        Ok(Self { storage, data_dir, lease_ms })
+    }
+}
@@ -0,0 +1,107 @@
+use crate::broker_service::ProtoCursor;
+use crate::filter::Filter;
+use std::collections::{BTreeSet, HashMap};
+
+/// Position of one subscriber within a topic: every offset before `next` is acked, and so
+/// are the offsets in `acked`, which all lie past `next`.
+#[derive(Debug, Clone, Default)]
+pub struct Cursor {
+    pub next: u64,
+    pub acked: BTreeSet<u64>,
+    /// Unacked offsets delivered to the subscriber at least once. Not persisted: after a
+    /// restart every unacked message is delivered again, counting from scratch.
+    pub deliveries: HashMap<u64, Delivery>,
+    /// Condition on the messages delivered to the subscriber; it acks the others unseen.
+    pub filter: Option<Filter>,
+    /// Whether the client subscribed by the topic's name, not only through wildcard patterns.
+    pub direct: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Delivery {
+    /// Until when the message is leased to the subscriber or, once nacked, from when on it
+    /// may be delivered again.
+    pub until: u64,
+    pub attempts: u32,
+    /// Reason given with the latest nack.
+    pub reason: String,
+    /// Whether `until` is a redelivery delay after a nack rather than the end of a lease.
+    pub nacked: bool,
+}
+
+impl Cursor {
+    pub fn new(next: u64, acked: BTreeSet<u64>) -> Self {
+        let mut cursor = Self { next, acked, deliveries: HashMap::new(), filter: None, direct: false };
+        cursor.compact();
+        cursor
+    }
+
+    pub fn is_acked(&self, offset: u64) -> bool {
+        offset < self.next || self.acked.contains(&offset)
+    }
+
+    pub fn is_leased(&self, offset: u64, now: u64) -> bool {
+        self.deliveries.get(&offset).is_some_and(|delivery| delivery.until > now)
+    }
+
+    /// Number of times `offset` has been delivered.
+    pub fn attempts(&self, offset: u64) -> u32 {
+        self.deliveries.get(&offset).map_or(0, |delivery| delivery.attempts)
+    }
+
+    /// Leases `offset` until `lease_until`, returning how many times it has been delivered.
+    pub fn deliver(&mut self, offset: u64, lease_until: u64) -> u32 {
+        let delivery = self.deliveries.entry(offset).or_default();
+        delivery.until = lease_until;
+        delivery.nacked = false;
+        delivery.attempts += 1;
+        delivery.attempts
+    }
+
+    /// Ends every lease the subscriber holds, so that the messages can be delivered again.
+    pub fn release_leases(&mut self) {
+        for delivery in self.deliveries.values_mut().filter(|delivery| !delivery.nacked) {
+            delivery.until = 0;
+        }
+    }
+
+    pub fn ack(&mut self, offset: u64) {
+        self.deliveries.remove(&offset);
+        if offset >= self.next {
+            self.acked.insert(offset);
+            self.compact();
+        }
+    }
+
+    /// Treats everything before `offset` as acked, as when those messages no longer exist.
+    pub fn advance_to(&mut self, offset: u64) {
+        if offset > self.next {
+            self.next = offset;
+            self.acked = self.acked.split_off(&offset);
+            self.deliveries.retain(|&delivered, _| delivered >= offset);
+            self.compact();
+        }
+    }
+
+    fn compact(&mut self) {
+        while self.acked.remove(&self.next) {
+            self.next += 1;
+        }
+    }
+
+    pub fn from_proto(proto: ProtoCursor) -> Self {
+        let mut cursor = Self::new(proto.next, proto.acked.into_iter().collect());
+        cursor.direct = proto.direct;
+        cursor.filter = Some(proto.filter).filter(|source| !source.is_empty()).and_then(|source| Filter::parse(&source).ok());
+        cursor
+    }
+
+    pub fn to_proto(&self) -> ProtoCursor {
+        ProtoCursor {
+            next: self.next,
+            acked: self.acked.iter().copied().collect(),
+            filter: self.filter.as_ref().map(|filter| filter.source().to_string()).unwrap_or_default(),
+            direct: self.direct,
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::broker_service::ProtoDedupEntry;
+use std::collections::{HashMap, VecDeque};
+
+/// Keys of the messages recently posted to a topic, with the offsets they were stored at, so
+/// that retried posts can be recognised.
+#[derive(Debug, Clone, Default)]
+pub struct DedupWindow {
+    offsets: HashMap<String, (u64, u64)>,
+    /// Keys in the order they were posted, with the time they were posted at.
+    posted: VecDeque<(u64, String)>,
+}
+
+impl DedupWindow {
+    /// Offset of the message posted with `key` at or after `since`, if any.
+    pub fn get(&self, key: &str, since: u64) -> Option<u64> {
+        match self.offsets.get(key) {
+            Some(&(offset, timestamp)) if timestamp >= since => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// Remembers `key` unless it is already known.
+    pub fn insert(&mut self, key: String, offset: u64, timestamp: u64) {
+        if !self.offsets.contains_key(&key) {
+            self.offsets.insert(key.clone(), (offset, timestamp));
+            self.posted.push_back((timestamp, key));
+        }
+    }
+
+    /// Forgets the keys posted before `since`.
+    pub fn expire(&mut self, since: u64) {
+        while self.posted.front().is_some_and(|(timestamp, _)| *timestamp < since) {
+            if let Some((_, key)) = self.posted.pop_front() {
+                self.offsets.remove(&key);
+            }
+        }
+    }
+
+    pub fn from_proto(proto: Vec<ProtoDedupEntry>) -> Self {
+        let mut window = Self::default();
+        for entry in proto {
+            window.insert(entry.key, entry.offset, entry.timestamp);
+        }
+        window
+    }
+
+    pub fn to_proto(&self) -> Vec<ProtoDedupEntry> {
+        self.posted
+            .iter()
+            .map(|(timestamp, key)| ProtoDedupEntry {
+                key: key.clone(),
+                offset: self.offsets[key].0,
+                timestamp: *timestamp,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,130 @@
+use crate::broker_service::{ProtoBroker, ProtoJournalEntry};
+use crate::journal::Journal;
+use crate::msg::Msg;
+use crate::segment::SegmentedLog;
+use crate::snapshot;
+use crate::storage::{topic_not_opened, Storage};
+use crate::topic::Retention;
+use crate::utils::escape_path_component;
+use prost::Message;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+const BROKER_STATE_FILE: &str = "broker_state.bin";
+const BROKER_JOURNAL_FILE: &str = "broker_journal.bin";
+const TOPICS_DIR: &str = "topics";
+
+/// Protobuf snapshot and journal files plus one segmented log per topic, all under `dir`.
+#[derive(Debug)]
+pub struct FileStorage {
+    state_file: String,
+    topics_dir: PathBuf,
+    journal: Journal,
+    unreplayed: Vec<ProtoJournalEntry>,
+    logs: HashMap<String, SegmentedLog>,
+}
+
+impl FileStorage {
+    pub fn open(dir: &str) -> Result<Self, Error> {
+        let dir = Path::new(dir);
+        let topics_dir = dir.join(TOPICS_DIR);
+        fs::create_dir_all(&topics_dir)?;
+        let journal_file = dir.join(BROKER_JOURNAL_FILE);
+        let (journal, unreplayed) = Journal::open(&journal_file.to_string_lossy())?;
+        Ok(Self {
+            state_file: dir.join(BROKER_STATE_FILE).to_string_lossy().into_owned(),
+            topics_dir,
+            journal,
+            unreplayed,
+            logs: HashMap::new(),
+        })
+    }
+
+    fn load_from_file(path: &str) -> Result<ProtoBroker, Error> {
+        let buf = snapshot::read(path)?;
+        ProtoBroker::decode(&buf[..]).map_err(|_| Error::new(ErrorKind::InvalidData, "broker state data file corrupted"))
+    }
+}
+
+impl Storage for FileStorage {
+    /// Falls back to the previous snapshot when the latest one is unreadable. Only a broker
+    /// that has never written a snapshot starts empty; anything else is an error, so a
+    /// damaged state file never silently discards all data.
+    fn load_snapshot(&mut self) -> Result<Option<ProtoBroker>, Error> {
+        let err = match Self::load_from_file(&self.state_file) {
+            Ok(proto) => return Ok(Some(proto)),
+            Err(e) => e,
+        };
+        let prev = snapshot::prev_path(&self.state_file);
+        match Self::load_from_file(&prev) {
+            Ok(proto) => {
+                warn!("Snapshot '{}' unreadable ({}), recovered from '{}'", self.state_file, err, prev);
+                Ok(Some(proto))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound && err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(_) => Err(err),
+        }
+    }
+
+    fn write_snapshot(&mut self, state: &ProtoBroker) -> Result<(), Error> {
+        snapshot::write(&self.state_file, &state.encode_to_vec())?;
+        self.journal.rotate()
+    }
+
+    fn load_journal(&mut self) -> Result<Vec<ProtoJournalEntry>, Error> {
+        Ok(std::mem::take(&mut self.unreplayed))
+    }
+
+    fn append_journal(&mut self, entry: &ProtoJournalEntry) -> Result<(), Error> {
+        self.journal.append(entry)
+    }
+
+    fn open_topic(&mut self, topic: &str) -> Result<(), Error> {
+        if !self.logs.contains_key(topic) {
+            let log = SegmentedLog::open(&self.topics_dir.join(escape_path_component(topic)))?;
+            self.logs.insert(topic.to_string(), log);
+        }
+        Ok(())
+    }
+
+    fn delete_topic(&mut self, topic: &str) -> Result<(), Error> {
+        self.logs.remove(topic);
+        match fs::remove_dir_all(self.topics_dir.join(escape_path_component(topic))) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn append_msg(&mut self, topic: &str, msg: &Msg) -> Result<u64, Error> {
+        self.logs.get_mut(topic).ok_or_else(|| topic_not_opened(topic))?.append(msg)
+    }
+
+    fn append_msgs(&mut self, topic: &str, msgs: &[Msg]) -> Result<u64, Error> {
+        self.logs.get_mut(topic).ok_or_else(|| topic_not_opened(topic))?.append_all(msgs)
+    }
+
+    fn read_msgs(&self, topic: &str, from: u64, max: usize) -> Result<Vec<Msg>, Error> {
+        self.logs.get(topic).ok_or_else(|| topic_not_opened(topic))?.read(from, max)
+    }
+
+    fn retention_start(&self, topic: &str, retention: &Retention, now: u64) -> Result<u64, Error> {
+        self.logs.get(topic).ok_or_else(|| topic_not_opened(topic))?.retention_start(retention, now)
+    }
+
+    /// Only whole segments are deleted, the rest of their messages stay on disk until the
+    /// segment holding them lies entirely before `start`.
+    fn truncate(&mut self, topic: &str, start: u64) -> Result<(), Error> {
+        self.logs.get_mut(topic).ok_or_else(|| topic_not_opened(topic))?.delete_before(start)
+    }
+
+    fn start_offset(&self, topic: &str) -> u64 {
+        self.logs.get(topic).map_or(0, SegmentedLog::start_offset)
+    }
+
+    fn next_offset(&self, topic: &str) -> u64 {
+        self.logs.get(topic).map_or(0, SegmentedLog::next_offset)
+    }
+}
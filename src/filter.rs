@@ -0,0 +1,474 @@
+use crate::msg::Msg;
+use serde_json::Value as Json;
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+
+/// Longest filter accepted, in bytes.
+const MAX_FILTER_LEN: usize = 4096;
+/// Deepest nesting of parentheses and `NOT`s accepted. Parsing, evaluating and dropping a
+/// filter all recurse, so together with `MAX_FILTER_TERMS` this bounds the stack they need.
+const MAX_FILTER_DEPTH: usize = 32;
+/// Most comparisons and `EXISTS` tests a filter may have.
+const MAX_FILTER_TERMS: usize = 64;
+
+/// Condition a subscriber puts on the messages it wants, such as
+/// `headers.region = 'eu' AND ($.amount >= 100 OR NOT EXISTS $.customer.id)`.
+///
+/// A comparison has a header (`headers.<name>`) or a JSON path into the payload (`$`, followed
+/// by `.<key>` and `[<index>]` steps) on its left, one of `=`, `!=`, `<`, `<=`, `>`, `>=` in
+/// the middle, and a string, number, `true`, `false` or `null` on its right. Header values are
+/// compared as numbers against numbers. A comparison with a missing header, a path not found
+/// or a payload that is not JSON is false; so is an ordering between values of different
+/// types, while they are always `!=`. `EXISTS` tests for a header or path on its own, and
+/// `AND`, `OR`, `NOT` and parentheses combine conditions.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(Operand),
+    Compare(Operand, Comparison, Value),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Header(String),
+    Path(Vec<Step>),
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+    /// A JSON array or object, which only `EXISTS` can test.
+    Composite,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Comparison(Comparison),
+    String(String),
+    Number(f64),
+    Word(String),
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        if source.len() > MAX_FILTER_LEN {
+            return Err(format!("longer than {} bytes", MAX_FILTER_LEN));
+        }
+        let mut parser = Parser { tokens: tokenize(source)?, pos: 0, depth: 0, terms: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {}", describe(Some(token))));
+        }
+        Ok(Self { source: source.to_string(), expr })
+    }
+
+    /// The expression the filter was parsed from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, msg: &Msg) -> bool {
+        let payload = OnceCell::new();
+        self.expr.eval(msg, &payload)
+    }
+}
+
+impl Expr {
+    /// `payload` caches the payload parsed as JSON, if it is JSON.
+    fn eval(&self, msg: &Msg, payload: &OnceCell<Option<Json>>) -> bool {
+        match self {
+            Expr::And(left, right) => left.eval(msg, payload) && right.eval(msg, payload),
+            Expr::Or(left, right) => left.eval(msg, payload) || right.eval(msg, payload),
+            Expr::Not(expr) => !expr.eval(msg, payload),
+            Expr::Exists(operand) => operand.resolve(msg, payload).is_some(),
+            Expr::Compare(operand, comparison, value) => match operand.resolve(msg, payload) {
+                Some(actual) => comparison.holds(compare(&actual, value)),
+                None => false,
+            },
+        }
+    }
+}
+
+impl Operand {
+    fn resolve(&self, msg: &Msg, payload: &OnceCell<Option<Json>>) -> Option<Value> {
+        match self {
+            Operand::Header(name) => msg.headers.get(name).map(|value| Value::String(value.clone())),
+            Operand::Path(steps) => {
+                let mut json = payload.get_or_init(|| serde_json::from_slice(&msg.payload).ok()).as_ref()?;
+                for step in steps {
+                    json = match step {
+                        Step::Key(key) => json.get(key)?,
+                        Step::Index(index) => json.get(index)?,
+                    };
+                }
+                Some(match json {
+                    Json::String(s) => Value::String(s.clone()),
+                    Json::Number(n) => Value::Number(n.as_f64()?),
+                    Json::Bool(b) => Value::Bool(*b),
+                    Json::Null => Value::Null,
+                    Json::Array(_) | Json::Object(_) => Value::Composite,
+                })
+            }
+        }
+    }
+}
+
+impl Comparison {
+    /// Whether the comparison holds for two values ordered as `ordering`, `None` meaning they
+    /// cannot be compared.
+    fn holds(self, ordering: Option<Ordering>) -> bool {
+        match ordering {
+            Some(ordering) => match self {
+                Comparison::Eq => ordering == Ordering::Equal,
+                Comparison::Ne => ordering != Ordering::Equal,
+                Comparison::Lt => ordering == Ordering::Less,
+                Comparison::Le => ordering != Ordering::Greater,
+                Comparison::Gt => ordering == Ordering::Greater,
+                Comparison::Ge => ordering != Ordering::Less,
+            },
+            None => self == Comparison::Ne,
+        }
+    }
+}
+
+fn compare(actual: &Value, expected: &Value) -> Option<Ordering> {
+    match (actual, expected) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::Number(b)) => a.trim().parse::<f64>().ok()?.partial_cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                tokens.push(Token::Comparison(match (c, or_equal) {
+                    ('=', _) => Comparison::Eq,
+                    ('!', true) => Comparison::Ne,
+                    ('<', false) => Comparison::Lt,
+                    ('<', true) => Comparison::Le,
+                    ('>', false) => Comparison::Gt,
+                    ('>', true) => Comparison::Ge,
+                    _ => return Err("expected '!='".to_string()),
+                }));
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => s.extend(chars.next()),
+                        Some(end) if end == c => break,
+                        Some(other) => s.push(other),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::String(s));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !"()=!<>'\"".contains(c)) {
+                    word.push(c);
+                }
+                let starts_number = word.starts_with(|c: char| c.is_ascii_digit() || c == '-');
+                tokens.push(match word.parse::<f64>() {
+                    Ok(n) if starts_number => Token::Number(n),
+                    _ if starts_number => return Err(format!("invalid number '{}'", word)),
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Names `token` in an error message.
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        None => "end of filter".to_string(),
+        Some(Token::Open) => "'('".to_string(),
+        Some(Token::Close) => "')'".to_string(),
+        Some(Token::Comparison(comparison)) => format!("{:?}", comparison),
+        Some(Token::String(s)) => format!("string '{}'", s),
+        Some(Token::Number(n)) => format!("number {}", n),
+        Some(Token::Word(word)) => format!("'{}'", word),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Parentheses and `NOT`s around the current position.
+    depth: usize,
+    /// Comparisons and `EXISTS` tests parsed so far.
+    terms: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consumes the next token if it is `keyword`, in any case.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    /// Runs `parse` one nesting level deeper.
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        if self.depth == MAX_FILTER_DEPTH {
+            return Err(format!("nested deeper than {} levels", MAX_FILTER_DEPTH));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn term(&mut self) -> Result<(), String> {
+        self.terms += 1;
+        if self.terms > MAX_FILTER_TERMS {
+            return Err(format!("more than {} conditions", MAX_FILTER_TERMS));
+        }
+        Ok(())
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.keyword("NOT") {
+            return self.nested(|parser| Ok(Expr::Not(Box::new(parser.unary()?))));
+        }
+        if self.keyword("EXISTS") {
+            self.term()?;
+            return Ok(Expr::Exists(self.operand()?));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let expr = self.nested(Self::or)?;
+            return match self.next() {
+                Some(Token::Close) => Ok(expr),
+                _ => Err("expected ')'".to_string()),
+            };
+        }
+        self.term()?;
+        let operand = self.operand()?;
+        let comparison = match self.next() {
+            Some(Token::Comparison(comparison)) => comparison,
+            other => return Err(format!("expected a comparison, found {}", describe(other.as_ref()))),
+        };
+        Ok(Expr::Compare(operand, comparison, self.value()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let word = match self.next() {
+            Some(Token::Word(word)) => word,
+            other => return Err(format!("expected a header or JSON path, found {}", describe(other.as_ref()))),
+        };
+        if let Some(name) = word.strip_prefix("headers.").filter(|name| !name.is_empty()) {
+            return Ok(Operand::Header(name.to_string()));
+        }
+        let Some(mut rest) = word.strip_prefix('$') else {
+            return Err(format!("expected a header or JSON path, found '{}'", word));
+        };
+        let mut steps = vec![];
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(format!("empty key in path '{}'", word));
+                }
+                steps.push(Step::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some((index, after)) = rest.strip_prefix('[').and_then(|after| after.split_once(']')) {
+                let index = index.parse().map_err(|_| format!("invalid index '{}' in path '{}'", index, word))?;
+                steps.push(Step::Index(index));
+                rest = after;
+            } else {
+                return Err(format!("invalid path '{}'", word));
+            }
+        }
+        Ok(Operand::Path(steps))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.next() {
+            Some(Token::String(s)) => Ok(Value::String(s)),
+            Some(Token::Number(n)) => Ok(Value::Number(n)),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("true") => Ok(Value::Bool(true)),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("false") => Ok(Value::Bool(false)),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("null") => Ok(Value::Null),
+            other => Err(format!("expected a value, found {}", describe(other.as_ref()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(headers: &[(&str, &str)], payload: &str) -> Msg {
+        let mut msg = Msg::new(payload.as_bytes());
+        for (name, value) in headers {
+            msg.headers.insert(name.to_string(), value.to_string());
+        }
+        msg
+    }
+
+    fn matches(filter: &str, msg: &Msg) -> bool {
+        Filter::parse(filter).unwrap().matches(msg)
+    }
+
+    #[test]
+    fn compares_headers() {
+        let msg = msg(&[("region", "eu"), ("priority", " 7 ")], "");
+        assert!(matches("headers.region = 'eu'", &msg));
+        assert!(matches("headers.region != \"us\"", &msg));
+        assert!(matches("headers.region < 'fr'", &msg));
+        assert!(matches("headers.priority >= 7", &msg));
+        assert!(!matches("headers.priority > 7", &msg));
+        assert!(!matches("headers.region > 1", &msg));
+        assert!(matches("headers.region != 1", &msg));
+        assert!(!matches("headers.missing = 'eu'", &msg));
+        assert!(!matches("headers.missing != 'eu'", &msg));
+    }
+
+    #[test]
+    fn follows_json_paths() {
+        let msg = msg(&[], r#"{"amount": 120.5, "paid": true, "note": null, "items": [{"sku": "a-1"}]}"#);
+        assert!(matches("$.amount > 100", &msg));
+        assert!(matches("$.amount = 120.5", &msg));
+        assert!(matches("$.paid = TRUE", &msg));
+        assert!(matches("$.note = null", &msg));
+        assert!(matches("$.items[0].sku = 'a-1'", &msg));
+        assert!(!matches("$.items[1].sku = 'a-1'", &msg));
+        assert!(!matches("$.amount = '120.5'", &msg));
+        assert!(matches("$.amount != '120.5'", &msg));
+        assert!(!matches("$.items = 1", &msg));
+    }
+
+    #[test]
+    fn paths_into_non_json_payloads_are_missing() {
+        let msg = msg(&[], "not json");
+        assert!(!matches("$.amount < 1000", &msg));
+        assert!(!matches("EXISTS $", &msg));
+        assert!(matches("NOT EXISTS $.amount", &msg));
+    }
+
+    #[test]
+    fn combines_conditions() {
+        let msg = msg(&[("region", "eu")], r#"{"amount": 50, "customer": {"id": 3}}"#);
+        assert!(matches("EXISTS headers.region AND EXISTS $.customer.id", &msg));
+        assert!(matches("EXISTS $.customer", &msg));
+        assert!(!matches("EXISTS $.customer.name", &msg));
+        assert!(matches("headers.region = 'eu' AND ($.amount >= 100 OR NOT EXISTS $.customer.name)", &msg));
+        assert!(!matches("headers.region = 'eu' AND NOT ($.amount < 100 OR EXISTS $.customer.name)", &msg));
+        assert!(matches("headers.region = 'us' or $.amount = 50", &msg));
+        assert!(!matches("headers.region = 'us' OR $.amount = 50 AND $.amount = 51", &msg));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let error = |filter: &str| Filter::parse(filter).unwrap_err();
+        assert_eq!(error("headers.a = 'x"), "unterminated string");
+        assert_eq!(error("headers.a ! 1"), "expected '!='");
+        assert_eq!(error("headers.a = 1x"), "invalid number '1x'");
+        assert_eq!(error("headers.a 1"), "expected a comparison, found number 1");
+        assert_eq!(error("region = 1"), "expected a header or JSON path, found 'region'");
+        assert_eq!(error("$.items[x] = 1"), "invalid index 'x' in path '$.items[x]'");
+        assert_eq!(error("$..a = 1"), "empty key in path '$..a'");
+        assert_eq!(error("headers.a = 1)"), "unexpected ')'");
+        assert_eq!(error("(headers.a = 1"), "expected ')'");
+        assert_eq!(error("headers.a ="), "expected a value, found end of filter");
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let nested = |depth: usize| format!("{}headers.a = 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Filter::parse(&nested(MAX_FILTER_DEPTH)).is_ok());
+        assert!(Filter::parse(&nested(MAX_FILTER_DEPTH + 1)).is_err());
+        assert!(Filter::parse(&nested(5_000)).is_err());
+        assert!(Filter::parse(&format!("{}headers.a = 1", "NOT ".repeat(MAX_FILTER_DEPTH + 1))).is_err());
+    }
+
+    #[test]
+    fn rejects_too_many_terms() {
+        let chain = |terms: usize| vec!["headers.a = 'x'"; terms].join(" AND ");
+        assert!(Filter::parse(&chain(MAX_FILTER_TERMS)).is_ok());
+        assert!(Filter::parse(&chain(MAX_FILTER_TERMS + 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_long_source() {
+        let padded = format!("headers.a = '{}'", "x".repeat(MAX_FILTER_LEN));
+        assert!(Filter::parse(&padded).is_err());
+    }
+}
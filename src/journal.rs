@@ -0,0 +1,174 @@
+use crate::broker_service::ProtoJournalEntry;
+use prost::Message;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use tracing::warn;
+
+const RECORD_HEADER_LEN: usize = 8;
+
+/// Append-only log of the operations applied to the broker since the last snapshot.
+/// Every record is a little-endian `u32` length and `u32` CRC32 of the body, followed by the
+/// body, an encoded `ProtoJournalEntry`.
+///
+/// On every snapshot the journal is moved aside to `<path>.prev` rather than discarded, so
+/// that the previous snapshot plus both journals still reproduce the latest state.
+#[derive(Debug)]
+pub struct Journal {
+    path: String,
+    file: File,
+}
+
+impl Journal {
+    /// Opens (or creates) the journal at `path` and returns it together with the entries
+    /// held by it and by the rotated journal before it, oldest first.
+    pub fn open(path: &str) -> Result<(Self, Vec<ProtoJournalEntry>), std::io::Error> {
+        let prev = prev_path(path);
+        let mut entries = match OpenOptions::new().read(true).write(true).open(&prev) {
+            Ok(mut file) => read_entries(&mut file, &prev)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        entries.extend(read_entries(&mut file, path)?);
+
+        Ok((Self { path: path.to_string(), file }, entries))
+    }
+
+    pub fn append(&mut self, entry: &ProtoJournalEntry) -> Result<(), std::io::Error> {
+        let body = entry.encode_to_vec();
+        let mut buf = Vec::with_capacity(RECORD_HEADER_LEN + body.len());
+        buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        buf.extend_from_slice(&body);
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Starts an empty journal, called once a snapshot covering every entry has been written.
+    pub fn rotate(&mut self) -> Result<(), std::io::Error> {
+        fs::rename(&self.path, prev_path(&self.path))?;
+        self.file = OpenOptions::new().read(true).append(true).create(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+fn prev_path(path: &str) -> String {
+    format!("{}.prev", path)
+}
+
+/// Decodes every record in `file`. A damaged record at the tail, left by a crash mid-append,
+/// is cut off; a damaged record followed by more data means the journal itself is corrupted.
+fn read_entries(file: &mut File, path: &str) -> Result<Vec<ProtoJournalEntry>, std::io::Error> {
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let Some(header) = buf.get(pos..pos + RECORD_HEADER_LEN) else { break };
+        let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let end = pos + RECORD_HEADER_LEN + len;
+        let Some(body) = buf.get(pos + RECORD_HEADER_LEN..end) else { break };
+        let entry = match ProtoJournalEntry::decode(body) {
+            Ok(entry) if crc32fast::hash(body) == checksum => entry,
+            _ if end == buf.len() => break,
+            _ => {
+                let msg = format!("Journal '{}' is corrupted at byte {}", path, pos);
+                return Err(std::io::Error::new(ErrorKind::InvalidData, msg));
+            }
+        };
+        entries.push(entry);
+        pos = end;
+    }
+
+    if pos < buf.len() {
+        warn!("Dropping {} bytes of incomplete journal tail in '{}'", buf.len() - pos, path);
+        file.set_len(pos as u64)?;
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker_service::proto_journal_entry::Op;
+    use crate::broker_service::CreateTopicRequest;
+    use crate::utils::TempDir;
+
+    fn entry(seq: u64) -> ProtoJournalEntry {
+        let create = CreateTopicRequest { name: format!("topic-{}", seq), ..Default::default() };
+        ProtoJournalEntry { seq, op: Some(Op::CreateTopic(create)) }
+    }
+
+    fn journal_path(dir: &TempDir) -> String {
+        fs::create_dir_all(dir.path()).unwrap();
+        format!("{}/journal", dir.path())
+    }
+
+    fn write_entries(path: &str, count: u64) {
+        let (mut journal, _) = Journal::open(path).unwrap();
+        for seq in 0..count {
+            journal.append(&entry(seq)).unwrap();
+        }
+    }
+
+    #[test]
+    fn reads_back_rotated_and_current_entries() {
+        let dir = TempDir::new();
+        let path = journal_path(&dir);
+        let (mut journal, entries) = Journal::open(&path).unwrap();
+        assert!(entries.is_empty());
+        journal.append(&entry(0)).unwrap();
+        journal.rotate().unwrap();
+        journal.append(&entry(1)).unwrap();
+        drop(journal);
+
+        let (_, entries) = Journal::open(&path).unwrap();
+        assert_eq!(entries, vec![entry(0), entry(1)]);
+    }
+
+    /// Writes three entries, damages the last one and checks that only it is dropped.
+    fn check_torn_tail(damage: impl Fn(&mut Vec<u8>)) {
+        let dir = TempDir::new();
+        let path = journal_path(&dir);
+        write_entries(&path, 3);
+        let mut buf = fs::read(&path).unwrap();
+        let kept_len = buf.len() / 3 * 2;
+        damage(&mut buf);
+        fs::write(&path, &buf).unwrap();
+
+        let (mut journal, entries) = Journal::open(&path).unwrap();
+        assert_eq!(entries, vec![entry(0), entry(1)]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), kept_len as u64);
+        journal.append(&entry(2)).unwrap();
+        drop(journal);
+        let (_, entries) = Journal::open(&path).unwrap();
+        assert_eq!(entries, vec![entry(0), entry(1), entry(2)]);
+    }
+
+    #[test]
+    fn cuts_off_a_torn_tail() {
+        // Cut short in the body, cut short in the header, and complete with a damaged body.
+        check_torn_tail(|buf| buf.truncate(buf.len() - 3));
+        check_torn_tail(|buf| buf.truncate(buf.len() / 3 * 2 + 5));
+        check_torn_tail(|buf| *buf.last_mut().unwrap() ^= 0xff);
+    }
+
+    #[test]
+    fn refuses_corruption_before_the_tail() {
+        let dir = TempDir::new();
+        let path = journal_path(&dir);
+        write_entries(&path, 3);
+        let mut buf = fs::read(&path).unwrap();
+        buf[RECORD_HEADER_LEN] ^= 0xff;
+        fs::write(&path, &buf).unwrap();
+
+        let err = Journal::open(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), buf);
+    }
+}
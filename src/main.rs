@@ -1,8 +1,21 @@
 mod broker;
+mod config;
+mod cursor;
+mod dedup;
+mod filter;
+mod file_storage;
+mod journal;
+mod memory_storage;
+mod segment;
+mod snapshot;
+mod sqlite_storage;
+mod storage;
 mod server;
 mod utils;
 mod topic;
+mod transaction;
 mod msg;
+mod wildcard;
 mod broker_service;
 
 use crate::server::start_server;
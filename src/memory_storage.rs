@@ -0,0 +1,120 @@
+use crate::broker_service::{ProtoBroker, ProtoJournalEntry};
+use crate::msg::Msg;
+use crate::storage::{topic_not_opened, Storage};
+use crate::topic::Retention;
+use prost::Message;
+use std::collections::{HashMap, VecDeque};
+use std::io::Error;
+
+/// Keeps everything in memory, so nothing survives a restart. Meant for tests and for
+/// deployments that value speed over durability.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    snapshot: Option<ProtoBroker>,
+    journal: Vec<ProtoJournalEntry>,
+    topics: HashMap<String, MemoryTopic>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryTopic {
+    msgs: VecDeque<Msg>,
+    /// Encoded size of the messages appended before each of `msgs`, ever since the topic
+    /// was opened.
+    bytes_before: VecDeque<u64>,
+    /// Encoded size of every message appended since the topic was opened.
+    bytes_appended: u64,
+    next_offset: u64,
+}
+
+impl Storage for MemoryStorage {
+    fn load_snapshot(&mut self) -> Result<Option<ProtoBroker>, Error> {
+        Ok(self.snapshot.clone())
+    }
+
+    fn write_snapshot(&mut self, state: &ProtoBroker) -> Result<(), Error> {
+        self.snapshot = Some(state.clone());
+        self.journal.clear();
+        Ok(())
+    }
+
+    fn load_journal(&mut self) -> Result<Vec<ProtoJournalEntry>, Error> {
+        Ok(self.journal.clone())
+    }
+
+    fn append_journal(&mut self, entry: &ProtoJournalEntry) -> Result<(), Error> {
+        self.journal.push(entry.clone());
+        Ok(())
+    }
+
+    fn open_topic(&mut self, topic: &str) -> Result<(), Error> {
+        self.topics.entry(topic.to_string()).or_default();
+        Ok(())
+    }
+
+    fn delete_topic(&mut self, topic: &str) -> Result<(), Error> {
+        self.topics.remove(topic);
+        Ok(())
+    }
+
+    fn append_msg(&mut self, topic: &str, msg: &Msg) -> Result<u64, Error> {
+        let stored = self.topics.get_mut(topic).ok_or_else(|| topic_not_opened(topic))?;
+        stored.bytes_before.push_back(stored.bytes_appended);
+        stored.bytes_appended += msg.to_proto().encoded_len() as u64;
+        let mut msg = msg.clone();
+        msg.offset = stored.next_offset;
+        stored.msgs.push_back(msg);
+        stored.next_offset += 1;
+        Ok(stored.next_offset - 1)
+    }
+
+    fn append_msgs(&mut self, topic: &str, msgs: &[Msg]) -> Result<u64, Error> {
+        let first = self.next_offset(topic);
+        for msg in msgs {
+            self.append_msg(topic, msg)?;
+        }
+        Ok(first)
+    }
+
+    fn read_msgs(&self, topic: &str, from: u64, max: usize) -> Result<Vec<Msg>, Error> {
+        let stored = self.topics.get(topic).ok_or_else(|| topic_not_opened(topic))?;
+        let first = stored.msgs.partition_point(|msg| msg.offset < from);
+        Ok(stored.msgs.iter().skip(first).take(max).cloned().collect())
+    }
+
+    fn retention_start(&self, topic: &str, retention: &Retention, now: u64) -> Result<u64, Error> {
+        let stored = self.topics.get(topic).ok_or_else(|| topic_not_opened(topic))?;
+        let next = stored.next_offset;
+        let mut start = self.start_offset(topic);
+        if retention.max_msgs > 0 {
+            start = start.max(next.saturating_sub(retention.max_msgs));
+        }
+        if retention.max_age_ms > 0 {
+            let cutoff = now.saturating_sub(retention.max_age_ms);
+            let first = stored.msgs.iter().find(|msg| msg.timestamp >= cutoff);
+            start = start.max(first.map_or(next, |msg| msg.offset));
+        }
+        if retention.max_bytes > 0 {
+            let cutoff = stored.bytes_appended.saturating_sub(retention.max_bytes);
+            let first = stored.bytes_before.partition_point(|&before| before < cutoff);
+            start = start.max(stored.msgs.get(first).map_or(next, |msg| msg.offset));
+        }
+        Ok(start)
+    }
+
+    fn truncate(&mut self, topic: &str, start: u64) -> Result<(), Error> {
+        let stored = self.topics.get_mut(topic).ok_or_else(|| topic_not_opened(topic))?;
+        while stored.msgs.front().is_some_and(|msg| msg.offset < start) {
+            stored.msgs.pop_front();
+            stored.bytes_before.pop_front();
+        }
+        Ok(())
+    }
+
+    fn start_offset(&self, topic: &str) -> u64 {
+        self.topics.get(topic).map_or(0, |stored| stored.msgs.front().map_or(stored.next_offset, |msg| msg.offset))
+    }
+
+    fn next_offset(&self, topic: &str) -> u64 {
+        self.topics.get(topic).map_or(0, |stored| stored.next_offset)
+    }
+}
@@ -1,31 +1,205 @@
-use crate::broker_service::ProtoMsg;
+use crate::broker_service::{ProtoDeadLettered, ProtoExpired, ProtoMsg};
+use crate::utils::now_millis;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub struct Msg {
-    pub payload: String,
+    pub payload: Vec<u8>,
     pub id: String,
+    /// Position within the topic, assigned when the message is appended to it.
+    pub offset: u64,
+    /// Milliseconds since the Unix epoch at which the message was posted.
+    pub timestamp: u64,
+    /// Deliveries to the client the message is being fetched by; 0 anywhere else.
+    pub attempts: u32,
+    pub dead_lettered: Option<DeadLettered>,
+    /// Milliseconds since the Unix epoch before which the message is not delivered; 0 to
+    /// deliver it right away.
+    pub deliver_at: u64,
+    /// Milliseconds since the Unix epoch from which on the message is no longer delivered; 0
+    /// if it never expires.
+    pub expires_at: u64,
+    pub expired: Option<Expired>,
+    pub headers: HashMap<String, String>,
+    pub producer_id: String,
+    pub sequence: u64,
+    pub idempotency_key: String,
+    /// Messages sharing a non-empty key are delivered to each subscriber one at a time, in order.
+    pub ordering_key: String,
+    /// Topic replies should be posted to, with the same `correlation_id`.
+    pub reply_to: String,
+    pub correlation_id: String,
 }
 
 impl Msg {
-    pub fn new(payload: &str) -> Self {
+    pub fn new(payload: &[u8]) -> Self {
         Self {
-            payload: payload.to_string(),
+            payload: payload.to_vec(),
             id: Uuid::new_v4().to_string(),
+            offset: 0,
+            timestamp: now_millis(),
+            attempts: 0,
+            dead_lettered: None,
+            deliver_at: 0,
+            expires_at: 0,
+            expired: None,
+            headers: HashMap::new(),
+            producer_id: String::new(),
+            sequence: 0,
+            idempotency_key: String::new(),
+            ordering_key: String::new(),
+            reply_to: String::new(),
+            correlation_id: String::new(),
         }
     }
 
+    /// Messages stored before payloads were binary only have the text `payload`.
     pub fn from_proto(proto: ProtoMsg) -> Self {
+        let payload = if proto.body.is_empty() { proto.payload.into_bytes() } else { proto.body };
         Self {
-            payload: proto.payload,
+            payload,
             id: proto.id,
+            offset: proto.offset,
+            timestamp: proto.timestamp,
+            attempts: proto.attempts,
+            dead_lettered: proto.dead_lettered.map(DeadLettered::from_proto),
+            deliver_at: proto.deliver_at,
+            expires_at: proto.expires_at,
+            expired: proto.expired.map(Expired::from_proto),
+            headers: proto.headers,
+            producer_id: proto.producer_id,
+            sequence: proto.sequence,
+            idempotency_key: proto.idempotency_key,
+            ordering_key: proto.ordering_key,
+            reply_to: proto.reply_to,
+            correlation_id: proto.correlation_id,
         }
     }
 
+    /// The form messages are stored in: a payload that is valid UTF-8 goes into the text
+    /// `payload`, any other into `body`.
     pub fn to_proto(&self) -> ProtoMsg {
+        let (payload, body) = match String::from_utf8(self.payload.clone()) {
+            Ok(text) => (text, vec![]),
+            Err(e) => (String::new(), e.into_bytes()),
+        };
         ProtoMsg {
-            payload: self.payload.clone(),
+            payload,
+            body,
             id: self.id.clone(),
+            offset: self.offset,
+            timestamp: self.timestamp,
+            attempts: self.attempts,
+            dead_lettered: self.dead_lettered.as_ref().map(DeadLettered::to_proto),
+            deliver_at: self.deliver_at,
+            expires_at: self.expires_at,
+            expired: self.expired.as_ref().map(Expired::to_proto),
+            headers: self.headers.clone(),
+            producer_id: self.producer_id.clone(),
+            sequence: self.sequence,
+            idempotency_key: self.idempotency_key.clone(),
+            ordering_key: self.ordering_key.clone(),
+            reply_to: self.reply_to.clone(),
+            correlation_id: self.correlation_id.clone(),
+        }
+    }
+
+    /// The form messages are fetched in, with `body` always set, as well as `payload` when it
+    /// is valid UTF-8.
+    pub fn to_fetched_proto(&self) -> ProtoMsg {
+        let mut proto = self.to_proto();
+        proto.body = self.payload.clone();
+        proto
+    }
+
+    /// Key identifying retries of the post of this message, if its producer gave one.
+    pub fn dedup_key(&self) -> Option<String> {
+        if !self.idempotency_key.is_empty() {
+            Some(format!("key:{}", self.idempotency_key))
+        } else if !self.producer_id.is_empty() {
+            Some(format!("seq:{}:{}", self.producer_id, self.sequence))
+        } else {
+            None
+        }
+    }
+
+    /// A new message with the payload and delivery details of this one, to forward it to
+    /// another topic. It carries no dedup key, so it is never taken for a retry there.
+    pub fn forwarded_copy(&self) -> Msg {
+        let mut copy = Msg::new(&self.payload);
+        copy.headers = self.headers.clone();
+        copy.ordering_key = self.ordering_key.clone();
+        copy.reply_to = self.reply_to.clone();
+        copy.correlation_id = self.correlation_id.clone();
+        copy
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at != 0 && self.expires_at <= now
+    }
+}
+
+/// Origin of a message moved to a dead-letter topic after too many delivery attempts.
+#[derive(Debug, Clone)]
+pub struct DeadLettered {
+    pub topic: String,
+    pub offset: u64,
+    pub msg_id: String,
+    pub client_id: String,
+    pub attempts: u32,
+    pub reason: String,
+}
+
+impl DeadLettered {
+    pub fn from_proto(proto: ProtoDeadLettered) -> Self {
+        Self {
+            topic: proto.topic,
+            offset: proto.offset,
+            msg_id: proto.msg_id,
+            client_id: proto.client_id,
+            attempts: proto.attempts,
+            reason: proto.reason,
+        }
+    }
+
+    pub fn to_proto(&self) -> ProtoDeadLettered {
+        ProtoDeadLettered {
+            topic: self.topic.clone(),
+            offset: self.offset,
+            msg_id: self.msg_id.clone(),
+            client_id: self.client_id.clone(),
+            attempts: self.attempts,
+            reason: self.reason.clone(),
+        }
+    }
+}
+
+/// Origin of a message moved to an expiry topic once its time to live had passed.
+#[derive(Debug, Clone)]
+pub struct Expired {
+    pub topic: String,
+    pub offset: u64,
+    pub msg_id: String,
+    pub expires_at: u64,
+}
+
+impl Expired {
+    pub fn from_proto(proto: ProtoExpired) -> Self {
+        Self {
+            topic: proto.topic,
+            offset: proto.offset,
+            msg_id: proto.msg_id,
+            expires_at: proto.expires_at,
+        }
+    }
+
+    pub fn to_proto(&self) -> ProtoExpired {
+        ProtoExpired {
+            topic: self.topic.clone(),
+            offset: self.offset,
+            msg_id: self.msg_id.clone(),
+            expires_at: self.expires_at,
         }
     }
 }
@@ -0,0 +1,568 @@
+use crate::broker_service::ProtoMsg;
+use crate::msg::Msg;
+use crate::topic::Retention;
+use prost::Message;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// A segment is rolled once its log file grows past this size.
+const MAX_SEGMENT_BYTES: u64 = 16 * 1024 * 1024;
+/// An index entry is written whenever this many log bytes were appended since the last one.
+const INDEX_INTERVAL_BYTES: u64 = 4096;
+/// offset (u64), timestamp (u64), body length (u32) and CRC32 of the body (u32).
+const RECORD_HEADER_LEN: usize = 8 + 8 + 4 + 4;
+/// relative offset (u32), file position (u32).
+const INDEX_ENTRY_LEN: usize = 4 + 4;
+/// timestamp (u64), relative offset (u32).
+const TIME_INDEX_ENTRY_LEN: usize = 8 + 4;
+
+/// The messages of one topic, stored on disk as a sequence of segments. Each segment
+/// `<base offset>.log` comes with a sparse `.index` mapping offsets to file positions and a
+/// `.timeindex` mapping timestamps to offsets, so reads only touch the segments they need
+/// and whole segments can be deleted once they fall out of retention.
+#[derive(Debug)]
+pub struct SegmentedLog {
+    dir: PathBuf,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug)]
+struct Segment {
+    base_offset: u64,
+    next_offset: u64,
+    size: u64,
+    max_timestamp: u64,
+    index: Vec<(u64, u64)>,
+    time_index: Vec<(u64, u64)>,
+    log_path: PathBuf,
+    files: Option<SegmentFiles>,
+}
+
+/// Append handles, only held open for the active segment.
+#[derive(Debug)]
+struct SegmentFiles {
+    log: File,
+    index: File,
+    time_index: File,
+}
+
+struct Record {
+    offset: u64,
+    timestamp: u64,
+    body: Vec<u8>,
+}
+
+impl SegmentedLog {
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(dir)?;
+
+        let mut base_offsets = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "log") {
+                if let Some(base_offset) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+                    base_offsets.push(base_offset);
+                }
+            }
+        }
+        base_offsets.sort();
+
+        let mut segments = Vec::with_capacity(base_offsets.len());
+        for (i, &base_offset) in base_offsets.iter().enumerate() {
+            segments.push(match base_offsets.get(i + 1) {
+                Some(&next_offset) => Segment::load(dir, base_offset, next_offset)?,
+                None => Segment::recover(dir, base_offset)?,
+            });
+        }
+        if segments.is_empty() {
+            segments.push(Segment::create(dir, 0)?);
+        }
+
+        Ok(Self { dir: dir.to_path_buf(), segments })
+    }
+
+    /// Offset of the oldest message still stored.
+    pub fn start_offset(&self) -> u64 {
+        self.segments[0].base_offset
+    }
+
+    /// Offset the next appended message will get.
+    pub fn next_offset(&self) -> u64 {
+        self.active().next_offset
+    }
+
+    /// Bytes taken up by the encoded messages, record headers excluded.
+    pub fn msg_bytes(&self) -> u64 {
+        self.segments.iter().map(Segment::msg_bytes).sum()
+    }
+
+    /// Appends `msg` and returns the offset it was stored at.
+    pub fn append(&mut self, msg: &Msg) -> Result<u64, Error> {
+        self.append_all(std::slice::from_ref(msg))
+    }
+
+    /// Appends `msgs`, syncing each segment written to once, and returns the offset the first
+    /// one was stored at.
+    pub fn append_all(&mut self, msgs: &[Msg]) -> Result<u64, Error> {
+        let first = self.next_offset();
+        for msg in msgs {
+            if self.active().size >= MAX_SEGMENT_BYTES {
+                self.segments.last_mut().unwrap().sync()?;
+                self.roll()?;
+            }
+            let body = msg.to_proto().encode_to_vec();
+            self.segments.last_mut().unwrap().write(msg.timestamp, &body)?;
+        }
+        self.segments.last_mut().unwrap().sync()?;
+        Ok(first)
+    }
+
+    /// Reads up to `max` messages starting at `from`, opening only the segments that hold them.
+    pub fn read(&self, from: u64, max: usize) -> Result<Vec<Msg>, Error> {
+        let from = from.max(self.start_offset());
+        let first = self.segments.partition_point(|segment| segment.next_offset <= from);
+
+        let mut msgs = vec![];
+        for segment in &self.segments[first..] {
+            if msgs.len() >= max {
+                break;
+            }
+            segment.read(from, max - msgs.len(), &mut msgs)?;
+        }
+        Ok(msgs)
+    }
+
+    /// First offset to keep so that the log satisfies `retention` at time `now`.
+    pub fn retention_start(&self, retention: &Retention, now: u64) -> Result<u64, Error> {
+        let next = self.next_offset();
+        let mut start = self.start_offset();
+        if retention.max_msgs > 0 {
+            start = start.max(next.saturating_sub(retention.max_msgs));
+        }
+        if retention.max_age_ms > 0 {
+            let cutoff = now.saturating_sub(retention.max_age_ms);
+            start = start.max(self.offset_for_timestamp(cutoff)?.unwrap_or(next));
+        }
+        if retention.max_bytes > 0 {
+            start = start.max(self.offset_for_size(retention.max_bytes)?);
+        }
+        Ok(start)
+    }
+
+    /// Offset of the first message stored at or after `timestamp`, if any.
+    pub fn offset_for_timestamp(&self, timestamp: u64) -> Result<Option<u64>, Error> {
+        let Some(position) = self.segments.iter().position(|segment| segment.max_timestamp >= timestamp) else {
+            return Ok(None);
+        };
+        let segment = &self.segments[position];
+        let i = segment.time_index.partition_point(|&(ts, _)| ts < timestamp);
+        let from = match i {
+            0 => segment.base_offset,
+            i => segment.time_index[i - 1].1,
+        };
+        let mut found = None;
+        segment.scan(from, |record| {
+            if record.timestamp >= timestamp {
+                found = Some(record.offset);
+                return false;
+            }
+            true
+        })?;
+        Ok(found.or_else(|| self.segments.get(position + 1).map(|segment| segment.base_offset)))
+    }
+
+    /// First offset from which the encoded messages take up at most `max_bytes`.
+    fn offset_for_size(&self, max_bytes: u64) -> Result<u64, Error> {
+        let mut total = self.msg_bytes();
+        for segment in &self.segments {
+            if total <= max_bytes {
+                return Ok(segment.base_offset);
+            }
+            if total - segment.msg_bytes() > max_bytes {
+                total -= segment.msg_bytes();
+                continue;
+            }
+            let mut start = segment.next_offset;
+            segment.scan(segment.base_offset, |record| {
+                if total <= max_bytes {
+                    start = record.offset;
+                    return false;
+                }
+                total -= record.body.len() as u64;
+                true
+            })?;
+            return Ok(start);
+        }
+        Ok(self.next_offset())
+    }
+
+    /// Deletes every segment whose messages all lie before `offset`. The active segment is
+    /// never deleted.
+    pub fn delete_before(&mut self, offset: u64) -> Result<(), Error> {
+        let deletable = self.segments.partition_point(|segment| segment.next_offset <= offset);
+        let deletable = deletable.min(self.segments.len() - 1);
+        for segment in self.segments.drain(..deletable) {
+            segment.delete()?;
+        }
+        Ok(())
+    }
+
+    fn active(&self) -> &Segment {
+        self.segments.last().unwrap()
+    }
+
+    fn roll(&mut self) -> Result<(), Error> {
+        let next_offset = self.next_offset();
+        self.segments.last_mut().unwrap().seal()?;
+        self.segments.push(Segment::create(&self.dir, next_offset)?);
+        Ok(())
+    }
+}
+
+impl Segment {
+    fn paths(dir: &Path, base_offset: u64) -> (PathBuf, PathBuf, PathBuf) {
+        let name = format!("{:020}", base_offset);
+        (
+            dir.join(format!("{}.log", name)),
+            dir.join(format!("{}.index", name)),
+            dir.join(format!("{}.timeindex", name)),
+        )
+    }
+
+    fn create(dir: &Path, base_offset: u64) -> Result<Self, Error> {
+        let (log_path, index_path, time_index_path) = Self::paths(dir, base_offset);
+        let files = SegmentFiles {
+            log: open_append(&log_path)?,
+            index: open_append(&index_path)?,
+            time_index: open_append(&time_index_path)?,
+        };
+        Ok(Self {
+            base_offset,
+            next_offset: base_offset,
+            size: 0,
+            max_timestamp: 0,
+            index: vec![],
+            time_index: vec![],
+            log_path,
+            files: Some(files),
+        })
+    }
+
+    /// Loads a sealed segment from its index files, rebuilding them if they are unusable.
+    fn load(dir: &Path, base_offset: u64, next_offset: u64) -> Result<Self, Error> {
+        let (log_path, index_path, time_index_path) = Self::paths(dir, base_offset);
+        let size = fs::metadata(&log_path)?.len();
+        let index = read_index(&index_path, INDEX_ENTRY_LEN, |e| {
+            let rel = u32::from_le_bytes(e[0..4].try_into().unwrap()) as u64;
+            let pos = u32::from_le_bytes(e[4..8].try_into().unwrap()) as u64;
+            (base_offset + rel, pos)
+        });
+        let time_index = read_index(&time_index_path, TIME_INDEX_ENTRY_LEN, |e| {
+            let ts = u64::from_le_bytes(e[0..8].try_into().unwrap());
+            let rel = u32::from_le_bytes(e[8..12].try_into().unwrap()) as u64;
+            (ts, base_offset + rel)
+        });
+
+        match (index, time_index) {
+            (Some(index), Some(time_index)) if !index.is_empty() || size == 0 => {
+                let max_timestamp = time_index.last().map_or(0, |&(ts, _)| ts);
+                Ok(Self {
+                    base_offset,
+                    next_offset,
+                    size,
+                    max_timestamp,
+                    index,
+                    time_index,
+                    log_path,
+                    files: None,
+                })
+            }
+            _ => {
+                warn!("Rebuilding indexes of segment '{}'", log_path.display());
+                let mut segment = Self::recover(dir, base_offset)?;
+                segment.seal()?;
+                Ok(segment)
+            }
+        }
+    }
+
+    /// Rebuilds a segment and its indexes by scanning its log, cutting off a torn tail.
+    fn recover(dir: &Path, base_offset: u64) -> Result<Self, Error> {
+        let (log_path, index_path, time_index_path) = Self::paths(dir, base_offset);
+        File::create(&index_path)?;
+        File::create(&time_index_path)?;
+
+        let mut segment = Self::create(dir, base_offset)?;
+        let len = fs::metadata(&log_path)?.len();
+        let mut reader = BufReader::new(File::open(&log_path)?);
+        while let Some(record) = read_record(&mut reader)? {
+            if record.offset != segment.next_offset {
+                break;
+            }
+            segment.track(record.offset, record.timestamp, segment.size)?;
+            segment.size += (RECORD_HEADER_LEN + record.body.len()) as u64;
+        }
+
+        if segment.size < len {
+            warn!("Dropping {} bytes of incomplete segment tail in '{}'", len - segment.size, log_path.display());
+            segment.files.as_mut().unwrap().log.set_len(segment.size)?;
+        }
+        Ok(segment)
+    }
+
+    /// Writes a record without syncing it to disk.
+    fn write(&mut self, timestamp: u64, body: &[u8]) -> Result<u64, Error> {
+        let offset = self.next_offset;
+        let mut buf = Vec::with_capacity(RECORD_HEADER_LEN + body.len());
+        buf.extend_from_slice(&offset.to_le_bytes());
+        buf.extend_from_slice(&timestamp.to_le_bytes());
+        buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&crc32fast::hash(body).to_le_bytes());
+        buf.extend_from_slice(body);
+
+        let files = self.files.as_mut().unwrap();
+        files.log.write_all(&buf)?;
+        self.track(offset, timestamp, self.size)?;
+        self.size += buf.len() as u64;
+        Ok(offset)
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.files.as_mut().unwrap().log.sync_data()
+    }
+
+    /// Accounts for a record written at `position`, adding index entries when due.
+    fn track(&mut self, offset: u64, timestamp: u64, position: u64) -> Result<(), Error> {
+        self.max_timestamp = self.max_timestamp.max(timestamp);
+        if self.index.is_empty() || position - self.index.last().unwrap().1 >= INDEX_INTERVAL_BYTES {
+            self.add_index_entries(offset, position)?;
+        }
+        self.next_offset = offset + 1;
+        Ok(())
+    }
+
+    fn add_index_entries(&mut self, offset: u64, position: u64) -> Result<(), Error> {
+        let rel = (offset - self.base_offset) as u32;
+        let files = self.files.as_mut().unwrap();
+
+        let mut entry = rel.to_le_bytes().to_vec();
+        entry.extend_from_slice(&(position as u32).to_le_bytes());
+        files.index.write_all(&entry)?;
+        self.index.push((offset, position));
+
+        let mut entry = self.max_timestamp.to_le_bytes().to_vec();
+        entry.extend_from_slice(&rel.to_le_bytes());
+        files.time_index.write_all(&entry)?;
+        self.time_index.push((self.max_timestamp, offset));
+        Ok(())
+    }
+
+    /// Closes the segment for appends, recording its final timestamp in the time index.
+    fn seal(&mut self) -> Result<(), Error> {
+        if self.next_offset > self.base_offset && self.time_index.last().map(|&(ts, _)| ts) != Some(self.max_timestamp) {
+            let last = self.next_offset - 1;
+            let mut entry = self.max_timestamp.to_le_bytes().to_vec();
+            entry.extend_from_slice(&((last - self.base_offset) as u32).to_le_bytes());
+            self.files.as_mut().unwrap().time_index.write_all(&entry)?;
+            self.time_index.push((self.max_timestamp, last));
+        }
+        if let Some(files) = self.files.take() {
+            files.index.sync_all()?;
+            files.time_index.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn read(&self, from: u64, max: usize, msgs: &mut Vec<Msg>) -> Result<(), Error> {
+        let mut remaining = max;
+        self.scan(from, |record| {
+            if record.offset < from {
+                return true;
+            }
+            match ProtoMsg::decode(&record.body[..]) {
+                Ok(proto) => {
+                    let mut msg = Msg::from_proto(proto);
+                    msg.offset = record.offset;
+                    msg.timestamp = record.timestamp;
+                    msgs.push(msg);
+                    remaining -= 1;
+                }
+                Err(_) => warn!("Skipping undecodable message at offset {}", record.offset),
+            }
+            remaining > 0
+        })
+    }
+
+    /// Calls `f` for every record from the indexed position closest to `from` until it
+    /// returns `false` or the segment ends.
+    fn scan(&self, from: u64, mut f: impl FnMut(Record) -> bool) -> Result<(), Error> {
+        let i = self.index.partition_point(|&(offset, _)| offset <= from);
+        let position = match i {
+            0 => 0,
+            i => self.index[i - 1].1,
+        };
+        let mut file = File::open(&self.log_path)?;
+        file.seek(SeekFrom::Start(position))?;
+        let mut reader = BufReader::new(file.take(self.size - position));
+        while let Some(record) = read_record(&mut reader)? {
+            if !f(record) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes taken up by the encoded messages of the segment, whose offsets are consecutive.
+    fn msg_bytes(&self) -> u64 {
+        self.size - (self.next_offset - self.base_offset) * RECORD_HEADER_LEN as u64
+    }
+
+    fn delete(self) -> Result<(), Error> {
+        fs::remove_file(&self.log_path)?;
+        fs::remove_file(self.log_path.with_extension("index"))?;
+        fs::remove_file(self.log_path.with_extension("timeindex"))?;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> Result<File, Error> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+/// Reads the next complete, uncorrupted record, or `None` at the end of the data.
+fn read_record(reader: &mut impl Read) -> Result<Option<Record>, Error> {
+    let mut header = [0u8; RECORD_HEADER_LEN];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let offset = u64::from_le_bytes(header[0..8].try_into().unwrap());
+    let timestamp = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let len = u32::from_le_bytes(header[16..20].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(header[20..24].try_into().unwrap());
+
+    let mut body = vec![0u8; len];
+    match reader.read_exact(&mut body) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    if crc32fast::hash(&body) != checksum {
+        return Ok(None);
+    }
+    Ok(Some(Record { offset, timestamp, body }))
+}
+
+/// Reads a fixed-width index file, or `None` if it is missing or has a partial entry.
+fn read_index(path: &Path, entry_len: usize, decode: impl Fn(&[u8]) -> (u64, u64)) -> Option<Vec<(u64, u64)>> {
+    let buf = fs::read(path).ok()?;
+    if buf.len() % entry_len != 0 {
+        return None;
+    }
+    Some(buf.chunks(entry_len).map(decode).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+
+    fn msgs(count: u64, payload_len: usize) -> Vec<Msg> {
+        (0..count)
+            .map(|i| {
+                let mut payload = i.to_string().into_bytes();
+                payload.resize(payload_len, b'.');
+                let mut msg = Msg::new(&payload);
+                msg.timestamp = 1000 + i * 10;
+                msg
+            })
+            .collect()
+    }
+
+    fn offsets(msgs: &[Msg]) -> Vec<u64> {
+        msgs.iter().map(|msg| msg.offset).collect()
+    }
+
+    fn log_path(dir: &Path, base_offset: u64, extension: &str) -> PathBuf {
+        dir.join(format!("{:020}.{}", base_offset, extension))
+    }
+
+    #[test]
+    fn reads_back_after_reopen() {
+        let dir = TempDir::new();
+        let dir = Path::new(dir.path());
+        let written = msgs(100, 8);
+        let mut log = SegmentedLog::open(dir).unwrap();
+        assert_eq!(log.append_all(&written[..99]).unwrap(), 0);
+        assert_eq!(log.append(&written[99]).unwrap(), 99);
+        drop(log);
+
+        let log = SegmentedLog::open(dir).unwrap();
+        assert_eq!((log.start_offset(), log.next_offset()), (0, 100));
+        let read = log.read(60, 3).unwrap();
+        assert_eq!(offsets(&read), [60, 61, 62]);
+        assert_eq!(read[0].id, written[60].id);
+        assert_eq!(read[0].payload, written[60].payload);
+        assert_eq!(read[0].timestamp, 1600);
+        assert!(log.read(100, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn cuts_off_a_torn_tail() {
+        let dir = TempDir::new();
+        let dir = Path::new(dir.path());
+        let mut log = SegmentedLog::open(dir).unwrap();
+        log.append_all(&msgs(10, 8)).unwrap();
+        drop(log);
+        let path = log_path(dir, 0, "log");
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+        let mut log = SegmentedLog::open(dir).unwrap();
+        assert_eq!(log.next_offset(), 9);
+        assert_eq!(log.append(&msgs(1, 8)[0]).unwrap(), 9);
+        assert_eq!(offsets(&log.read(0, usize::MAX).unwrap()), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rolls_segments_and_rebuilds_their_indexes() {
+        let dir = TempDir::new();
+        let dir = Path::new(dir.path());
+        let mut log = SegmentedLog::open(dir).unwrap();
+        log.append_all(&msgs(20, 1024 * 1024)).unwrap();
+        assert_eq!(log.segments.iter().map(|segment| segment.base_offset).collect::<Vec<_>>(), [0, 16]);
+        drop(log);
+        fs::write(log_path(dir, 0, "index"), [0u8; 3]).unwrap();
+
+        let mut log = SegmentedLog::open(dir).unwrap();
+        assert_eq!((log.start_offset(), log.next_offset()), (0, 20));
+        assert_eq!(offsets(&log.read(15, 2).unwrap()), [15, 16]);
+        assert_eq!(log.offset_for_timestamp(1095).unwrap(), Some(10));
+
+        log.delete_before(18).unwrap();
+        assert_eq!(log.start_offset(), 16);
+        assert!(!log_path(dir, 0, "log").exists());
+        log.delete_before(20).unwrap();
+        assert_eq!(log.start_offset(), 16);
+    }
+
+    #[test]
+    fn finds_where_retention_starts() {
+        let dir = TempDir::new();
+        let mut log = SegmentedLog::open(Path::new(dir.path())).unwrap();
+        log.append_all(&msgs(100, 8)).unwrap();
+
+        assert_eq!(log.offset_for_timestamp(1505).unwrap(), Some(51));
+        assert_eq!(log.offset_for_timestamp(5000).unwrap(), None);
+        let retention = |max_age_ms, max_bytes, max_msgs| Retention { max_age_ms, max_bytes, max_msgs };
+        assert_eq!(log.retention_start(&retention(0, 0, 0), 5000).unwrap(), 0);
+        assert_eq!(log.retention_start(&retention(0, 0, 30), 5000).unwrap(), 70);
+        assert_eq!(log.retention_start(&retention(100, 0, 0), 1990).unwrap(), 89);
+        let record_bytes = log.msg_bytes() / 100;
+        assert_eq!(log.retention_start(&retention(0, 10 * record_bytes, 0), 5000).unwrap(), 90);
+        assert_eq!(log.retention_start(&retention(100, 0, 30), 1990).unwrap(), 89);
+    }
+}
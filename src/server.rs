@@ -1,35 +1,88 @@
-use std::collections::HashSet;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tonic::{Code, Request, Response, Status};
+use std::pin::pin;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
 use tonic::transport::Server;
-use crate::broker::{Broker, BrokerError};
-use crate::broker_service::{CreateTopicRequest, CreateTopicResponse, SubscribeRequest, SubscribeResponse, UnsubscribeRequest, UnsubscribeResponse, PostRequest, PostResponse, FetchRequest, FetchResponse, AckRequest, AckResponse};
+use crate::broker::{Broker, BrokerError, Posted};
+use crate::config::Config;
+use crate::msg::Msg;
+use crate::storage;
+use crate::topic::{FetchBudget, TopicConfig};
+use crate::utils::now_millis;
+use crate::broker_service::{CreateTopicRequest, CreateTopicResponse, SubscribeRequest, SubscribeResponse, UnsubscribeRequest, UnsubscribeResponse, PostRequest, PostResponse, PostBatchRequest, PostBatchResponse, PostResult, FetchRequest, FetchFromRequest, FetchResponse, StreamRequest, ConsumeRequest, ProtoMsg, AckRequest, AckResponse, AckBatchRequest, AckBatchResponse, AckResult, ExtendLeaseRequest, ExtendLeaseResponse, NackRequest, NackResponse, BeginTransactionRequest, BeginTransactionResponse, CommitTransactionRequest, CommitTransactionResponse, AbortTransactionRequest, AbortTransactionResponse, CreateReplyTopicRequest, CreateReplyTopicResponse, PostAndWaitRequest, PostAndWaitResponse};
 use crate::broker_service::broker_service_server::{BrokerService, BrokerServiceServer};
-use tracing::{info};
+use tracing::{info, warn};
+use uuid::Uuid;
 
-const BROKER_STATE_FILE: &str = "broker_state.bin";
 const SERVER_ADDR: &str = "127.0.0.1:5005";
+/// Number of messages `FetchFrom` returns when the request sets no limit.
+const FETCH_FROM_DEFAULT_MAX: usize = 100;
+/// How often a waiting `Fetch` looks for messages that became due without being posted, such
+/// as scheduled or nacked ones.
+const FETCH_RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest a `Fetch` or `PostAndWait` waits, whatever the request asks for, so that its
+/// deadline cannot overflow.
+const MAX_WAIT: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long `PostAndWait` waits for a reply when the request sets no timeout.
+const POST_AND_WAIT_DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Messages pushed by `Stream` that may wait for the client to take them.
+const STREAM_BUFFER: usize = 64;
+/// How often topic retention limits are enforced and expired messages dropped.
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Default)]
 pub struct BrokerServiceImpl {
     broker: Arc<Mutex<Broker>>,
+    config: Config,
+}
+
+impl BrokerServiceImpl {
+    fn lease_ms(&self, requested: u64) -> u64 {
+        match requested {
+            0 => self.config.lease_ms,
+            lease_ms => lease_ms,
+        }
+    }
+}
+
+impl From<BrokerError> for Status {
+    fn from(e: BrokerError) -> Self {
+        match e {
+            BrokerError::TopicAlreadyExists(_) => Status::already_exists(e.to_string()),
+            BrokerError::TopicNotFound(_)
+            | BrokerError::MessageNotFound(_)
+            | BrokerError::TransactionNotFound(_)
+            | BrokerError::NotSubscribed(..) => {
+                Status::not_found(e.to_string())
+            }
+            BrokerError::InvalidTopicConfig(..) | BrokerError::InvalidFilter(..) | BrokerError::InvalidPattern(..) => {
+                Status::invalid_argument(e.to_string())
+            }
+            BrokerError::LeaseNotFound(..) | BrokerError::NotPending(..) => Status::failed_precondition(e.to_string()),
+            BrokerError::Storage(_) => Status::internal(e.to_string()),
+        }
+    }
 }
 
 #[tonic::async_trait]
 impl BrokerService for BrokerServiceImpl {
     async fn create_topic(&self, request: Request<CreateTopicRequest>) -> Result<Response<CreateTopicResponse>, Status> {
         let req = request.into_inner();
+        if !req.reply_owner.is_empty() {
+            return Err(Status::invalid_argument("Reply topics can only be created with CreateReplyTopic"));
+        }
         let mut broker = self.broker.lock().await;
 
-        match broker.create_topic(&req.name).await {
+        match broker.create_topic(&req.name, TopicConfig::from_request(&req)).await {
             Ok(_) => {
-                broker.save_to_file(BROKER_STATE_FILE).await.unwrap();
                 Ok(Response::new(CreateTopicResponse {
                     message: format!("Topic '{}' created", req.name),
                 }))
             }
-            Err(e) => Err(Status::already_exists(e.to_string())),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -37,15 +90,14 @@ impl BrokerService for BrokerServiceImpl {
         let req = request.into_inner();
         let mut broker = self.broker.lock().await;
 
-        match broker.subscribe(&req.topic_name, &req.client_id).await {
+        match broker.subscribe(&req.topic_name, &req.client_id, &req.filter).await {
             Ok(_) => {
                 info!("Subscription: {:?}", &req);
-                broker.save_to_file(BROKER_STATE_FILE).await.unwrap();
                 Ok(Response::new(SubscribeResponse {
                     message: format!("Client '{}' subscribed to '{}'", req.client_id, req.topic_name),
                 }))
             }
-            Err(e) => Err(Status::not_found(e.to_string())),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -56,12 +108,11 @@ impl BrokerService for BrokerServiceImpl {
         match broker.unsubscribe(&req.topic_name, &req.client_id).await {
             Ok(_) => {
                 info!("Unsubscription: {:?}", &req);
-                broker.save_to_file(BROKER_STATE_FILE).await.unwrap();
                 Ok(Response::new(UnsubscribeResponse {
                     message: format!("Client '{}' unsubscribed from '{}'", req.client_id, req.topic_name),
                 }))
             }
-            Err(e) => Err(Status::not_found(e.to_string())),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -69,59 +120,309 @@ impl BrokerService for BrokerServiceImpl {
         let req = request.into_inner();
         let mut broker = self.broker.lock().await;
 
-        match broker.post(&req.topic_name, &req.payload).await {
-            Ok(_) => {
+        let msg = msg_from_request(&req).map_err(Status::invalid_argument)?;
+        if !req.transaction_id.is_empty() {
+            broker.post_in_transaction(&req.transaction_id, &req.topic_name, msg).await?;
+            return Ok(Response::new(PostResponse {
+                message: format!("Added to transaction '{}'", req.transaction_id),
+                offset: 0,
+                duplicate: false,
+            }));
+        }
+        let message = format!("Posted to '{}': '{}'", req.topic_name, String::from_utf8_lossy(&msg.payload));
+        match broker.post(&req.topic_name, msg).await {
+            Ok(Posted { offset, duplicate: true }) => {
+                info!("Duplicate post: {:?}", &req);
+                Ok(Response::new(PostResponse {
+                    message: format!("Already posted to '{}' at offset {}", req.topic_name, offset),
+                    offset,
+                    duplicate: true,
+                }))
+            }
+            Ok(Posted { offset, duplicate: false }) => {
                 info!("Post: {:?}", &req);
-                broker.save_to_file(BROKER_STATE_FILE).await.unwrap();
                 Ok(Response::new(PostResponse {
-                    message: format!("Posted to '{}': '{}'", req.topic_name, req.payload),
+                    message,
+                    offset,
+                    duplicate: false,
                 }))
             }
-            Err(e) => Err(Status::not_found(e.to_string())),
+            Err(e) => Err(e.into()),
         }
     }
 
+    async fn post_batch(&self, request: Request<PostBatchRequest>) -> Result<Response<PostBatchResponse>, Status> {
+        let req = request.into_inner();
+        let mut broker = self.broker.lock().await;
+
+        let mut results = Vec::with_capacity(req.posts.len());
+        let mut posts = vec![];
+        let mut posted_at = vec![];
+        for post in &req.posts {
+            let msg = match msg_from_request(post) {
+                Ok(msg) => msg,
+                Err(error) => {
+                    results.push(PostResult { error: error.to_string(), ..Default::default() });
+                    continue;
+                }
+            };
+            if !post.transaction_id.is_empty() {
+                let added = broker.post_in_transaction(&post.transaction_id, &post.topic_name, msg).await;
+                results.push(PostResult { error: added.err().map(|e| e.to_string()).unwrap_or_default(), ..Default::default() });
+                continue;
+            }
+            posted_at.push(results.len());
+            results.push(PostResult::default());
+            posts.push((post.topic_name.clone(), msg));
+        }
+        for (i, posted) in posted_at.into_iter().zip(broker.post_batch(posts).await) {
+            results[i] = match posted {
+                Ok(posted) => PostResult { offset: posted.offset, duplicate: posted.duplicate, error: String::new() },
+                Err(e) => PostResult { error: e.to_string(), ..Default::default() },
+            };
+        }
+        info!("PostBatch: {} posts", req.posts.len());
+        Ok(Response::new(PostBatchResponse { results }))
+    }
+
     // TODO: So we are sending always a vector, even if all the request is wrong
     // think about it once more...
     async fn fetch(&self, request: Request<FetchRequest>) -> Result<Response<FetchResponse>, Status> {
         let req = request.into_inner();
-        let mut broker = self.broker.lock().await;
-        let msgs = broker.fetch(&req.client_id).await;
-        let mut proto_msgs = vec![];
-        for m in msgs {
-            proto_msgs.push(m.to_proto());
+        let lease_ms = self.lease_ms(req.lease_ms);
+        let budget = FetchBudget::new(req.max_msgs, req.max_bytes);
+        let deadline = Instant::now() + Duration::from_millis(req.wait_ms).min(MAX_WAIT);
+        loop {
+            let mut broker = self.broker.lock().await;
+            // Registered before fetching, so that a post right after is not missed.
+            let posted = broker.posted.clone();
+            let mut notified = pin!(posted.notified());
+            notified.as_mut().enable();
+            let msgs = broker.fetch(&req.client_id, lease_ms, budget).await?;
+            drop(broker);
+
+            let now = Instant::now();
+            if !msgs.is_empty() || now >= deadline {
+                let mut proto_msgs = vec![];
+                for m in msgs {
+                    proto_msgs.push(m.to_fetched_proto());
+                }
+                return Ok(Response::new(FetchResponse {
+                    msgs: proto_msgs,
+                }));
+            }
+            let _ = tokio::time::timeout(FETCH_RECHECK_INTERVAL.min(deadline - now), notified).await;
+        }
+    }
+
+    async fn fetch_from(&self, request: Request<FetchFromRequest>) -> Result<Response<FetchResponse>, Status> {
+        let req = request.into_inner();
+        let max = match req.max_msgs {
+            0 => FETCH_FROM_DEFAULT_MAX,
+            max => max as usize,
+        };
+        let broker = self.broker.lock().await;
+        match broker.fetch_from(&req.topic_name, req.offset, max).await {
+            Ok(msgs) => Ok(Response::new(FetchResponse {
+                msgs: msgs.iter().map(Msg::to_fetched_proto).collect(),
+            })),
+            Err(e) => Err(e.into()),
         }
-        Ok(Response::new(FetchResponse {
-            msgs: proto_msgs,
-        }))
+    }
+
+    type StreamStream = ReceiverStream<Result<ProtoMsg, Status>>;
+
+    async fn stream(&self, request: Request<StreamRequest>) -> Result<Response<Self::StreamStream>, Status> {
+        let req = request.into_inner();
+        let lease_ms = self.lease_ms(req.lease_ms);
+        self.broker.lock().await.release_leases(&req.client_id).await;
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        info!("Streaming to '{}'", req.client_id);
+        let broker = self.broker.clone();
+        tokio::spawn(async move {
+            push(broker.clone(), &req.client_id, lease_ms, tx).await;
+            disconnected(&broker, &req.client_id).await;
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type ConsumeStream = ReceiverStream<Result<ProtoMsg, Status>>;
+
+    async fn consume(&self, request: Request<Streaming<ConsumeRequest>>) -> Result<Response<Self::ConsumeStream>, Status> {
+        let mut requests = request.into_inner();
+        let first = requests
+            .message()
+            .await?
+            .filter(|first| !first.client_id.is_empty())
+            .ok_or_else(|| Status::invalid_argument("The first request must name the client"))?;
+        let lease_ms = self.lease_ms(first.lease_ms);
+        self.broker.lock().await.release_leases(&first.client_id).await;
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        info!("Consumer '{}' connected", first.client_id);
+        let broker = self.broker.clone();
+        tokio::spawn(async move {
+            let client_id = first.client_id.clone();
+            consume(broker.clone(), first, requests, lease_ms, tx).await;
+            disconnected(&broker, &client_id).await;
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
 
     async fn ack(&self, request: Request<AckRequest>) -> Result<Response<AckResponse>, Status> {
         let req = request.into_inner();
         let mut broker = self.broker.lock().await;
-        match broker.ack(&req.msg_id, &req.client_id).await {
+        let acked = match req.transaction_id.as_str() {
+            "" => broker.ack(&req.msg_id, &req.client_id).await,
+            transaction_id => broker.ack_in_transaction(transaction_id, &req.msg_id, &req.client_id).await,
+        };
+        match acked {
             Ok(_) => {
-                match broker.save_to_file(BROKER_STATE_FILE).await {
-                    Ok(x) => x,
-                    Err(_) => todo!(),
-                };
                 Ok(Response::new(AckResponse { message: "Ok".to_string(), }))
             },
-            Err(e) => Err(Status::not_found(e.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn ack_batch(&self, request: Request<AckBatchRequest>) -> Result<Response<AckBatchResponse>, Status> {
+        let req = request.into_inner();
+        let mut broker = self.broker.lock().await;
+
+        let mut results = Vec::with_capacity(req.acks.len());
+        let mut acks = vec![];
+        let mut acked_at = vec![];
+        for ack in req.acks {
+            if !ack.transaction_id.is_empty() {
+                let added = broker.ack_in_transaction(&ack.transaction_id, &ack.msg_id, &ack.client_id).await;
+                results.push(AckResult { error: added.err().map(|e| e.to_string()).unwrap_or_default() });
+                continue;
+            }
+            acked_at.push(results.len());
+            results.push(AckResult::default());
+            acks.push((ack.msg_id, ack.client_id));
+        }
+        for (i, acked) in acked_at.into_iter().zip(broker.ack_batch(acks).await) {
+            if let Err(e) = acked {
+                results[i].error = e.to_string();
+            }
+        }
+        Ok(Response::new(AckBatchResponse { results }))
+    }
+
+    async fn extend_lease(&self, request: Request<ExtendLeaseRequest>) -> Result<Response<ExtendLeaseResponse>, Status> {
+        let req = request.into_inner();
+        let mut broker = self.broker.lock().await;
+        match broker.extend_lease(&req.msg_id, &req.client_id, self.lease_ms(req.lease_ms)).await {
+            Ok(_) => Ok(Response::new(ExtendLeaseResponse { message: "Ok".to_string() })),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn nack(&self, request: Request<NackRequest>) -> Result<Response<NackResponse>, Status> {
+        let req = request.into_inner();
+        let mut broker = self.broker.lock().await;
+        match broker.nack(&req.msg_id, &req.client_id, req.delay_ms, &req.reason).await {
+            Ok(_) => Ok(Response::new(NackResponse { message: "Ok".to_string() })),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn begin_transaction(&self, _request: Request<BeginTransactionRequest>) -> Result<Response<BeginTransactionResponse>, Status> {
+        let mut broker = self.broker.lock().await;
+        let transaction_id = broker.begin_transaction().await;
+        info!("Transaction '{}' begun", transaction_id);
+        Ok(Response::new(BeginTransactionResponse { transaction_id }))
+    }
+
+    async fn commit_transaction(&self, request: Request<CommitTransactionRequest>) -> Result<Response<CommitTransactionResponse>, Status> {
+        let req = request.into_inner();
+        let mut broker = self.broker.lock().await;
+        let offsets = broker.commit_transaction(&req.transaction_id).await?;
+        info!("Transaction '{}' committed", req.transaction_id);
+        Ok(Response::new(CommitTransactionResponse {
+            message: format!("Transaction '{}' committed", req.transaction_id),
+            offsets,
+        }))
+    }
+
+    async fn abort_transaction(&self, request: Request<AbortTransactionRequest>) -> Result<Response<AbortTransactionResponse>, Status> {
+        let req = request.into_inner();
+        let mut broker = self.broker.lock().await;
+        broker.abort_transaction(&req.transaction_id).await?;
+        info!("Transaction '{}' aborted", req.transaction_id);
+        Ok(Response::new(AbortTransactionResponse {
+            message: format!("Transaction '{}' aborted", req.transaction_id),
+        }))
+    }
+
+    async fn create_reply_topic(&self, request: Request<CreateReplyTopicRequest>) -> Result<Response<CreateReplyTopicResponse>, Status> {
+        let req = request.into_inner();
+        if req.client_id.is_empty() {
+            return Err(Status::invalid_argument("The request must name the client"));
+        }
+        let mut broker = self.broker.lock().await;
+        let topic_name = broker.reply_topic(&req.client_id).await?;
+        Ok(Response::new(CreateReplyTopicResponse { topic_name }))
+    }
+
+    async fn post_and_wait(&self, request: Request<PostAndWaitRequest>) -> Result<Response<PostAndWaitResponse>, Status> {
+        let req = request.into_inner();
+        if req.client_id.is_empty() {
+            return Err(Status::invalid_argument("The request must name the client"));
+        }
+        let mut post = req.post.ok_or_else(|| Status::invalid_argument("The request must carry a post"))?;
+        if !post.transaction_id.is_empty() {
+            return Err(Status::invalid_argument("A post awaiting its reply cannot be part of a transaction"));
+        }
+        if post.correlation_id.is_empty() {
+            post.correlation_id = Uuid::new_v4().to_string();
+        }
+        let timeout = match req.timeout_ms {
+            0 => POST_AND_WAIT_DEFAULT_TIMEOUT,
+            timeout_ms => Duration::from_millis(timeout_ms),
+        };
+        let deadline = Instant::now() + timeout.min(MAX_WAIT);
+
+        let mut broker = self.broker.lock().await;
+        post.reply_to = broker.reply_topic(&req.client_id).await?;
+        let _waiting = broker.await_reply(&req.client_id).await;
+        let msg = msg_from_request(&post).map_err(Status::invalid_argument)?;
+        broker.post(&post.topic_name, msg).await?;
+        drop(broker);
+        info!("PostAndWait: {:?}", &post);
+
+        loop {
+            let mut broker = self.broker.lock().await;
+            let posted = broker.posted.clone();
+            let mut notified = pin!(posted.notified());
+            notified.as_mut().enable();
+            let reply = broker.take_reply(&post.reply_to, &req.client_id, &post.correlation_id).await?;
+            drop(broker);
+
+            if let Some(reply) = reply {
+                return Ok(Response::new(PostAndWaitResponse { reply: Some(reply.to_fetched_proto()) }));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Status::deadline_exceeded(format!(
+                    "No reply with correlation id '{}' within {} ms",
+                    post.correlation_id,
+                    timeout.as_millis(),
+                )));
+            }
+            let _ = tokio::time::timeout(FETCH_RECHECK_INTERVAL.min(deadline - now), notified).await;
         }
     }
 }
 
 pub async fn start_server() -> Result<(), Box<dyn std::error::Error>> {
     let addr = SERVER_ADDR.parse()?;
-    let broker = match Broker::load_from_file(BROKER_STATE_FILE).await {
-        Ok(broker) => {
-            info!("{:#?}", broker.clone());
-            Arc::new(Mutex::new(broker))
-        },
-        Err(_) => Arc::new(Mutex::new(Broker::new())),
-    };
-    let broker_service = BrokerServiceImpl { broker };
+    let config = Config::from_env()?;
+    info!("{:?}", config);
+    let broker = Broker::open(storage::open(&config)?).await?;
+    info!("{:#?}", broker);
+    let broker = Arc::new(Mutex::new(broker));
+    tokio::spawn(housekeeping(broker.clone()));
+    let broker_service = BrokerServiceImpl { broker, config };
 
     info!("Server started. Listening on {}", addr);
 
@@ -132,3 +433,151 @@ pub async fn start_server() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Pushes the messages fetched for `client_id` to `tx` as they become available, for as long
+/// as the client keeps the stream open.
+async fn push(broker: Arc<Mutex<Broker>>, client_id: &str, lease_ms: u64, tx: mpsc::Sender<Result<ProtoMsg, Status>>) {
+    loop {
+        let mut locked = broker.lock().await;
+        let posted = locked.posted.clone();
+        let mut notified = pin!(posted.notified());
+        notified.as_mut().enable();
+        // Fetch no more than fits into the buffer, so pushed messages are not leased for long
+        // before the client gets them.
+        let budget = FetchBudget::new(tx.capacity().max(1) as u32, 0);
+        let fetched = locked.fetch(client_id, lease_ms, budget).await;
+        drop(locked);
+
+        let msgs = match fetched {
+            Ok(msgs) => msgs,
+            Err(e) => {
+                let _ = tx.send(Err(e.into())).await;
+                return;
+            }
+        };
+        if msgs.is_empty() {
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep(FETCH_RECHECK_INTERVAL) => {}
+                _ = tx.closed() => break,
+            }
+        }
+        for msg in msgs {
+            if tx.send(Ok(msg.to_fetched_proto())).await.is_err() {
+                break;
+            }
+        }
+        if tx.is_closed() {
+            break;
+        }
+    }
+    info!("Stopped streaming to '{}'", client_id);
+}
+
+/// Serves a `Consume` stream opened with request `first`: pushes messages to `tx` as long as
+/// the client has credit left, and applies the credits and acks of its requests, until it
+/// closes the stream.
+async fn consume(
+    broker: Arc<Mutex<Broker>>,
+    first: ConsumeRequest,
+    mut requests: Streaming<ConsumeRequest>,
+    lease_ms: u64,
+    tx: mpsc::Sender<Result<ProtoMsg, Status>>,
+) {
+    let client_id = first.client_id.clone();
+    let mut credits = 0u64;
+    let mut request = Some(first);
+    loop {
+        let mut locked = broker.lock().await;
+        if let Some(req) = request.take() {
+            credits += req.credits as u64;
+            let acks = req.acks.into_iter().map(|msg_id| (msg_id, client_id.clone())).collect();
+            for e in locked.ack_batch(acks).await.into_iter().filter_map(Result::err) {
+                warn!("Consumer '{}' failed to ack: {}", client_id, e);
+            }
+        }
+        let posted = locked.posted.clone();
+        let mut notified = pin!(posted.notified());
+        notified.as_mut().enable();
+        let mut msgs = vec![];
+        if credits > 0 {
+            let budget = FetchBudget::new(credits.min(u32::MAX as u64) as u32, 0);
+            match locked.fetch(&client_id, lease_ms, budget).await {
+                Ok(fetched) => msgs = fetched,
+                Err(e) => {
+                    let _ = tx.send(Err(e.into())).await;
+                    return;
+                }
+            }
+        }
+        drop(locked);
+
+        credits -= msgs.len() as u64;
+        for msg in msgs {
+            if tx.send(Ok(msg.to_fetched_proto())).await.is_err() {
+                return;
+            }
+        }
+        tokio::select! {
+            next = requests.message() => match next {
+                Ok(Some(next)) => request = Some(next),
+                _ => break,
+            },
+            _ = notified, if credits > 0 => {}
+            _ = tokio::time::sleep(FETCH_RECHECK_INTERVAL), if credits > 0 => {}
+        }
+    }
+    info!("Consumer '{}' disconnected", client_id);
+}
+
+/// Cleans up after `client_id` closed a `Stream` or `Consume` stream: its reply topic goes
+/// away with it.
+async fn disconnected(broker: &Mutex<Broker>, client_id: &str) {
+    if let Err(e) = broker.lock().await.delete_reply_topic(client_id).await {
+        warn!("Deleting the reply topic of '{}' failed: {}", client_id, e);
+    }
+}
+
+/// Builds the message a post request asks for, or says why the request is invalid.
+fn msg_from_request(req: &PostRequest) -> Result<Msg, &'static str> {
+    let mut msg = match (req.payload.is_empty(), req.body.is_empty()) {
+        (_, true) => Msg::new(req.payload.as_bytes()),
+        (true, false) => Msg::new(&req.body),
+        (false, false) => return Err("Set either payload or body, not both"),
+    };
+    msg.headers = req.headers.clone();
+    msg.producer_id = req.producer_id.clone();
+    msg.sequence = req.sequence;
+    msg.idempotency_key = req.idempotency_key.clone();
+    msg.ordering_key = req.ordering_key.clone();
+    msg.reply_to = req.reply_to.clone();
+    msg.correlation_id = req.correlation_id.clone();
+    msg.deliver_at = match (req.deliver_at, req.delay_ms) {
+        (0, 0) => 0,
+        (0, delay_ms) => now_millis().saturating_add(delay_ms),
+        (deliver_at, _) => deliver_at,
+    };
+    if req.ttl_ms > 0 {
+        msg.expires_at = msg.timestamp.max(msg.deliver_at).saturating_add(req.ttl_ms);
+    }
+    Ok(msg)
+}
+
+/// Periodic maintenance of the broker, running for as long as the server does.
+async fn housekeeping(broker: Arc<Mutex<Broker>>) {
+    let mut interval = tokio::time::interval(HOUSEKEEPING_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut broker = broker.lock().await;
+        if let Err(e) = broker.enforce_retention().await {
+            warn!("Enforcing retention failed: {}", e);
+        }
+        if let Err(e) = broker.expire_msgs().await {
+            warn!("Expiring messages failed: {}", e);
+        }
+        broker.abort_stale_transactions().await;
+        if let Err(e) = broker.delete_idle_reply_topics().await {
+            warn!("Deleting idle reply topics failed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"SPBK";
+const SNAPSHOT_VERSION: u32 = 1;
+/// magic, version (u32), body length (u64) and CRC32 of the body (u32), all little-endian.
+const HEADER_LEN: usize = 4 + 4 + 8 + 4;
+
+/// Path of the snapshot that was current before the last successful write of `path`.
+pub fn prev_path(path: &str) -> String {
+    format!("{}.prev", path)
+}
+
+/// Writes `body` to `path` without ever leaving a partially written file behind: the data
+/// goes to a temporary file that is fsynced and then renamed over `path`. The snapshot being
+/// replaced is kept at `prev_path(path)`.
+pub fn write(path: &str, body: &[u8]) -> Result<(), Error> {
+    let tmp = format!("{}.tmp", path);
+    let mut buf = Vec::with_capacity(HEADER_LEN + body.len());
+    buf.extend_from_slice(SNAPSHOT_MAGIC);
+    buf.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    buf.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    buf.extend_from_slice(&crc32fast::hash(body).to_le_bytes());
+    buf.extend_from_slice(body);
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(&buf)?;
+    file.sync_all()?;
+
+    let prev = prev_path(path);
+    if Path::new(path).exists() {
+        let _ = fs::remove_file(&prev);
+        fs::hard_link(path, &prev)?;
+    }
+    fs::rename(&tmp, path)?;
+    sync_dir(path)
+}
+
+/// Reads the snapshot body stored at `path`, verifying its header and checksum.
+/// Files written before the header was introduced are returned as they are, unless empty:
+/// an interrupted write of that format left an empty file, which would otherwise read as a
+/// broker without any state.
+pub fn read(path: &str) -> Result<Vec<u8>, Error> {
+    let mut file = File::open(path)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    if buf.is_empty() {
+        return Err(corrupted(path, "empty file"));
+    }
+    if !buf.starts_with(SNAPSHOT_MAGIC) {
+        return Ok(buf);
+    }
+    if buf.len() < HEADER_LEN {
+        return Err(corrupted(path, "truncated header"));
+    }
+
+    let version = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+    if version != SNAPSHOT_VERSION {
+        return Err(corrupted(path, &format!("unsupported version {}", version)));
+    }
+    let len = u64::from_le_bytes(buf[8..16].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(buf[16..20].try_into().unwrap());
+    let body = &buf[HEADER_LEN..];
+    if body.len() != len {
+        return Err(corrupted(path, &format!("expected {} bytes, found {}", len, body.len())));
+    }
+    if crc32fast::hash(body) != checksum {
+        return Err(corrupted(path, "checksum mismatch"));
+    }
+    Ok(body.to_vec())
+}
+
+fn corrupted(path: &str, reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("snapshot '{}' corrupted: {}", path, reason))
+}
+
+fn sync_dir(path: &str) -> Result<(), Error> {
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker_service::ProtoBroker;
+    use crate::file_storage::FileStorage;
+    use crate::storage::Storage;
+    use crate::utils::TempDir;
+    use prost::Message;
+
+    fn snapshot_path(dir: &TempDir) -> String {
+        Path::new(dir.path()).join("broker_state.bin").to_string_lossy().into_owned()
+    }
+
+    /// Writes `body` as a snapshot, applies `damage` to the file and reads it back.
+    fn read_damaged(body: &[u8], damage: impl FnOnce(&mut Vec<u8>)) -> Result<Vec<u8>, Error> {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.path()).unwrap();
+        let path = snapshot_path(&dir);
+        write(&path, body).unwrap();
+        let mut buf = fs::read(&path).unwrap();
+        damage(&mut buf);
+        fs::write(&path, buf).unwrap();
+        read(&path)
+    }
+
+    fn is_corrupted(result: Result<Vec<u8>, Error>) -> bool {
+        result.is_err_and(|e| e.kind() == ErrorKind::InvalidData)
+    }
+
+    #[test]
+    fn reads_back_what_it_wrote() {
+        assert_eq!(read_damaged(b"state", |_| {}).unwrap(), b"state");
+        assert_eq!(read_damaged(b"legacy", |buf| *buf = b"legacy".to_vec()).unwrap(), b"legacy");
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        assert!(is_corrupted(read_damaged(b"state", |buf| *buf.last_mut().unwrap() ^= 1)));
+        assert!(is_corrupted(read_damaged(b"state", |buf| buf[4] = 2)));
+        assert!(is_corrupted(read_damaged(b"state", |buf| buf.truncate(HEADER_LEN - 1))));
+        assert!(is_corrupted(read_damaged(b"state", |buf| buf.truncate(buf.len() - 1))));
+        assert!(is_corrupted(read_damaged(b"state", |buf| buf.clear())));
+    }
+
+    #[test]
+    fn falls_back_to_the_previous_snapshot() {
+        let dir = TempDir::new();
+        let mut storage = FileStorage::open(dir.path()).unwrap();
+        for journal_seq in [1, 2] {
+            storage.write_snapshot(&ProtoBroker { journal_seq, ..ProtoBroker::default() }).unwrap();
+        }
+        let prev = read(&prev_path(&snapshot_path(&dir))).unwrap();
+        assert_eq!(ProtoBroker::decode(&prev[..]).unwrap().journal_seq, 1);
+
+        fs::write(snapshot_path(&dir), b"").unwrap();
+        assert_eq!(storage.load_snapshot().unwrap().unwrap().journal_seq, 1);
+
+        fs::write(prev_path(&snapshot_path(&dir)), b"").unwrap();
+        assert!(storage.load_snapshot().is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::broker_service::{ProtoBroker, ProtoJournalEntry, ProtoMsg};
+use crate::msg::Msg;
+use crate::storage::{topic_not_opened, Storage};
+use crate::topic::Retention;
+use prost::Message;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+const DATABASE_FILE: &str = "broker.sqlite";
+
+/// Everything in a single embedded SQLite database under `dir`. Every write is its own
+/// transaction, and a snapshot replaces the journal entries it covers atomically. The first
+/// and next offset of every topic are kept in their own table, as the messages left after a
+/// truncation may not tell them. Every message also records the encoded size of the messages
+/// appended to its topic before it, so that size retention is a single indexed lookup. The
+/// connection sits behind a mutex only because `Storage` has to be `Sync`.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+    /// First and next offset of every opened topic.
+    offsets: HashMap<String, (u64, u64)>,
+    /// Encoded size of the messages appended to every opened topic, counted as in the
+    /// `bytes_before` of its messages.
+    bytes_appended: HashMap<String, u64>,
+}
+
+impl SqliteStorage {
+    pub fn open(dir: &str) -> Result<Self, Error> {
+        fs::create_dir_all(dir)?;
+        let conn = Connection::open(Path::new(dir).join(DATABASE_FILE)).map_err(sql_error)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = FULL;
+             CREATE TABLE IF NOT EXISTS snapshot (id INTEGER PRIMARY KEY CHECK (id = 0), state BLOB NOT NULL);
+             CREATE TABLE IF NOT EXISTS journal (seq INTEGER PRIMARY KEY, entry BLOB NOT NULL);
+             CREATE TABLE IF NOT EXISTS messages (
+                 topic TEXT NOT NULL,
+                 offset INTEGER NOT NULL,
+                 timestamp INTEGER NOT NULL,
+                 bytes_before INTEGER NOT NULL,
+                 body BLOB NOT NULL,
+                 PRIMARY KEY (topic, offset)
+             );
+             CREATE INDEX IF NOT EXISTS messages_by_timestamp ON messages (topic, timestamp);
+             CREATE INDEX IF NOT EXISTS messages_by_bytes_before ON messages (topic, bytes_before);
+             CREATE TABLE IF NOT EXISTS topics (
+                 name TEXT PRIMARY KEY,
+                 start_offset INTEGER NOT NULL,
+                 next_offset INTEGER NOT NULL
+             );",
+        ).map_err(sql_error)?;
+        Ok(Self { conn: Mutex::new(conn), offsets: HashMap::new(), bytes_appended: HashMap::new() })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Records `start` and `next` as the first and next offset of `topic`.
+fn save_offsets(conn: &Connection, topic: &str, start: u64, next: u64) -> Result<(), Error> {
+    conn.execute(
+        "INSERT INTO topics (name, start_offset, next_offset) VALUES (?1, ?2, ?3)
+         ON CONFLICT (name) DO UPDATE SET start_offset = ?2, next_offset = ?3",
+        params![topic, start as i64, next as i64],
+    )
+    .map_err(sql_error)?;
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn load_snapshot(&mut self) -> Result<Option<ProtoBroker>, Error> {
+        let state: Option<Vec<u8>> = self.conn()
+            .query_row("SELECT state FROM snapshot WHERE id = 0", [], |row| row.get(0))
+            .optional()
+            .map_err(sql_error)?;
+        state.map(|state| ProtoBroker::decode(&state[..]).map_err(decode_error)).transpose()
+    }
+
+    fn write_snapshot(&mut self, state: &ProtoBroker) -> Result<(), Error> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(sql_error)?;
+        tx.execute("INSERT OR REPLACE INTO snapshot (id, state) VALUES (0, ?1)", params![state.encode_to_vec()])
+            .map_err(sql_error)?;
+        tx.execute("DELETE FROM journal WHERE seq <= ?1", params![state.journal_seq as i64])
+            .map_err(sql_error)?;
+        tx.commit().map_err(sql_error)
+    }
+
+    fn load_journal(&mut self) -> Result<Vec<ProtoJournalEntry>, Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT entry FROM journal ORDER BY seq").map_err(sql_error)?;
+        let rows = stmt.query_map([], |row| row.get::<_, Vec<u8>>(0)).map_err(sql_error)?;
+        rows.map(|entry| ProtoJournalEntry::decode(&entry.map_err(sql_error)?[..]).map_err(decode_error))
+            .collect()
+    }
+
+    fn append_journal(&mut self, entry: &ProtoJournalEntry) -> Result<(), Error> {
+        self.conn()
+            .execute("INSERT INTO journal (seq, entry) VALUES (?1, ?2)", params![entry.seq as i64, entry.encode_to_vec()])
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    /// Topics stored before their offsets were recorded get them from their messages.
+    fn open_topic(&mut self, topic: &str) -> Result<(), Error> {
+        if self.offsets.contains_key(topic) {
+            return Ok(());
+        }
+        let conn = self.conn();
+        let recorded: Option<(i64, i64)> = conn
+            .query_row("SELECT start_offset, next_offset FROM topics WHERE name = ?1", params![topic], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()
+            .map_err(sql_error)?;
+        let (start, next) = match recorded {
+            Some((start, next)) => (start as u64, next as u64),
+            None => {
+                let (first, last): (Option<i64>, Option<i64>) = conn
+                    .query_row("SELECT MIN(offset), MAX(offset) FROM messages WHERE topic = ?1", params![topic], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })
+                    .map_err(sql_error)?;
+                let next = last.map_or(0, |last| last as u64 + 1);
+                let start = first.map_or(next, |first| first as u64);
+                save_offsets(&conn, topic, start, next)?;
+                (start, next)
+            }
+        };
+        let bytes_appended: Option<i64> = conn
+            .query_row(
+                "SELECT bytes_before + length(body) FROM messages WHERE topic = ?1 ORDER BY offset DESC LIMIT 1",
+                params![topic],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        drop(conn);
+        self.offsets.insert(topic.to_string(), (start, next));
+        self.bytes_appended.insert(topic.to_string(), bytes_appended.unwrap_or(0) as u64);
+        Ok(())
+    }
+
+    fn delete_topic(&mut self, topic: &str) -> Result<(), Error> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(sql_error)?;
+        tx.execute("DELETE FROM messages WHERE topic = ?1", params![topic]).map_err(sql_error)?;
+        tx.execute("DELETE FROM topics WHERE name = ?1", params![topic]).map_err(sql_error)?;
+        tx.commit().map_err(sql_error)?;
+        drop(conn);
+        self.offsets.remove(topic);
+        self.bytes_appended.remove(topic);
+        Ok(())
+    }
+
+    fn append_msg(&mut self, topic: &str, msg: &Msg) -> Result<u64, Error> {
+        self.append_msgs(topic, std::slice::from_ref(msg))
+    }
+
+    fn append_msgs(&mut self, topic: &str, msgs: &[Msg]) -> Result<u64, Error> {
+        let (start, next) = self.offsets.get_mut(topic).ok_or_else(|| topic_not_opened(topic))?;
+        let bytes_appended = self.bytes_appended.get_mut(topic).ok_or_else(|| topic_not_opened(topic))?;
+        let first = *next;
+        let mut bytes = *bytes_appended;
+        let conn = self.conn.get_mut().unwrap_or_else(|e| e.into_inner());
+        let tx = conn.transaction().map_err(sql_error)?;
+        {
+            let mut stmt = tx
+                .prepare_cached("INSERT INTO messages (topic, offset, timestamp, bytes_before, body) VALUES (?1, ?2, ?3, ?4, ?5)")
+                .map_err(sql_error)?;
+            for (i, msg) in msgs.iter().enumerate() {
+                let offset = first + i as u64;
+                let body = msg.to_proto().encode_to_vec();
+                stmt.execute(params![topic, offset as i64, msg.timestamp as i64, bytes as i64, body])
+                    .map_err(sql_error)?;
+                bytes += body.len() as u64;
+            }
+        }
+        save_offsets(&tx, topic, *start, first + msgs.len() as u64)?;
+        tx.commit().map_err(sql_error)?;
+        *next += msgs.len() as u64;
+        *bytes_appended = bytes;
+        Ok(first)
+    }
+
+    fn read_msgs(&self, topic: &str, from: u64, max: usize) -> Result<Vec<Msg>, Error> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached("SELECT offset, timestamp, body FROM messages WHERE topic = ?1 AND offset >= ?2 ORDER BY offset LIMIT ?3")
+            .map_err(sql_error)?;
+        let limit = max.min(i64::MAX as usize) as i64;
+        let rows = stmt
+            .query_map(params![topic, from as i64, limit], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Vec<u8>>(2)?))
+            })
+            .map_err(sql_error)?;
+        rows.map(|row| {
+            let (offset, timestamp, body) = row.map_err(sql_error)?;
+            let mut msg = Msg::from_proto(ProtoMsg::decode(&body[..]).map_err(decode_error)?);
+            msg.offset = offset as u64;
+            msg.timestamp = timestamp as u64;
+            Ok(msg)
+        })
+        .collect()
+    }
+
+    fn retention_start(&self, topic: &str, retention: &Retention, now: u64) -> Result<u64, Error> {
+        let &(first, next) = self.offsets.get(topic).ok_or_else(|| topic_not_opened(topic))?;
+        let conn = self.conn();
+        let mut start = first;
+        if retention.max_msgs > 0 {
+            start = start.max(next.saturating_sub(retention.max_msgs));
+        }
+        if retention.max_age_ms > 0 {
+            let cutoff = now.saturating_sub(retention.max_age_ms);
+            let first_kept: Option<i64> = conn
+                .query_row(
+                    "SELECT MIN(offset) FROM messages WHERE topic = ?1 AND timestamp >= ?2",
+                    params![topic, cutoff as i64],
+                    |row| row.get(0),
+                )
+                .map_err(sql_error)?;
+            start = start.max(first_kept.map_or(next, |offset| offset as u64));
+        }
+        if retention.max_bytes > 0 {
+            let bytes_appended = self.bytes_appended.get(topic).copied().unwrap_or(0);
+            let cutoff = bytes_appended.saturating_sub(retention.max_bytes);
+            let first_kept: Option<i64> = conn
+                .query_row(
+                    "SELECT offset FROM messages WHERE topic = ?1 AND bytes_before >= ?2 ORDER BY bytes_before LIMIT 1",
+                    params![topic, cutoff as i64],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(sql_error)?;
+            start = start.max(first_kept.map_or(next, |offset| offset as u64));
+        }
+        Ok(start)
+    }
+
+    fn truncate(&mut self, topic: &str, start: u64) -> Result<(), Error> {
+        let (first, next) = self.offsets.get_mut(topic).ok_or_else(|| topic_not_opened(topic))?;
+        let conn = self.conn.get_mut().unwrap_or_else(|e| e.into_inner());
+        let tx = conn.transaction().map_err(sql_error)?;
+        tx.execute("DELETE FROM messages WHERE topic = ?1 AND offset < ?2", params![topic, start as i64])
+            .map_err(sql_error)?;
+        save_offsets(&tx, topic, (*first).max(start), *next)?;
+        tx.commit().map_err(sql_error)?;
+        *first = (*first).max(start);
+        Ok(())
+    }
+
+    fn start_offset(&self, topic: &str) -> u64 {
+        self.offsets.get(topic).map_or(0, |&(first, _)| first)
+    }
+
+    fn next_offset(&self, topic: &str) -> u64 {
+        self.offsets.get(topic).map_or(0, |&(_, next)| next)
+    }
+}
+
+fn sql_error(e: rusqlite::Error) -> Error {
+    Error::other(e)
+}
+
+fn decode_error(e: prost::DecodeError) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,166 @@
+use crate::broker_service::{ProtoBroker, ProtoJournalEntry};
+use crate::config::{Config, StorageKind};
+use crate::file_storage::FileStorage;
+use crate::memory_storage::MemoryStorage;
+use crate::msg::Msg;
+use crate::sqlite_storage::SqliteStorage;
+use crate::topic::Retention;
+use std::fmt::Debug;
+use std::io::Error;
+
+/// Where the broker keeps its durable state: the latest snapshot, the journal of operations
+/// applied since, and the messages of every topic.
+pub trait Storage: Send + Sync + Debug {
+    /// Returns the latest snapshot, or `None` if none has been written yet.
+    fn load_snapshot(&mut self) -> Result<Option<ProtoBroker>, Error>;
+
+    /// Persists `state`; the journal entries it covers are no longer needed afterwards.
+    fn write_snapshot(&mut self, state: &ProtoBroker) -> Result<(), Error>;
+
+    /// Returns the journal entries written before the storage was opened, oldest first.
+    /// Entries already covered by the snapshot may be included.
+    fn load_journal(&mut self) -> Result<Vec<ProtoJournalEntry>, Error>;
+
+    fn append_journal(&mut self, entry: &ProtoJournalEntry) -> Result<(), Error>;
+
+    /// Prepares the message storage of `topic`, keeping any messages it already holds.
+    fn open_topic(&mut self, topic: &str) -> Result<(), Error>;
+
+    /// Deletes `topic` together with all of its messages.
+    fn delete_topic(&mut self, topic: &str) -> Result<(), Error>;
+
+    /// Stores `msg` at the end of `topic` and returns the offset it was given.
+    fn append_msg(&mut self, topic: &str, msg: &Msg) -> Result<u64, Error>;
+
+    /// Stores `msgs` at the end of `topic` in a single write and returns the offset the first
+    /// one was given.
+    fn append_msgs(&mut self, topic: &str, msgs: &[Msg]) -> Result<u64, Error>;
+
+    /// Reads up to `max` messages of `topic` starting at offset `from`.
+    fn read_msgs(&self, topic: &str, from: u64, max: usize) -> Result<Vec<Msg>, Error>;
+
+    /// First offset of `topic` to keep so that it satisfies `retention` at time `now`.
+    fn retention_start(&self, topic: &str, retention: &Retention, now: u64) -> Result<u64, Error>;
+
+    /// Discards the messages of `topic` before offset `start`. A backend may hold on to some
+    /// of them until it can remove them wholesale.
+    fn truncate(&mut self, topic: &str, start: u64) -> Result<(), Error>;
+
+    /// Offset of the oldest message of `topic` still stored.
+    fn start_offset(&self, topic: &str) -> u64;
+
+    /// Offset the next message appended to `topic` will get.
+    fn next_offset(&self, topic: &str) -> u64;
+}
+
+pub fn open(config: &Config) -> Result<Box<dyn Storage>, Error> {
+    Ok(match config.storage {
+        StorageKind::Memory => Box::new(MemoryStorage::default()),
+        StorageKind::File => Box::new(FileStorage::open(&config.data_dir)?),
+        StorageKind::Sqlite => Box::new(SqliteStorage::open(&config.data_dir)?),
+    })
+}
+
+pub fn topic_not_opened(topic: &str) -> Error {
+    Error::new(std::io::ErrorKind::NotFound, format!("storage for topic '{}' not opened", topic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+    use prost::Message;
+
+    fn msgs(count: u64) -> Vec<Msg> {
+        (0..count)
+            .map(|i| {
+                let mut msg = Msg::new(format!("m{}", i).as_bytes());
+                msg.timestamp = 1000 + i * 10;
+                msg
+            })
+            .collect()
+    }
+
+    fn offsets(msgs: &[Msg]) -> Vec<u64> {
+        msgs.iter().map(|msg| msg.offset).collect()
+    }
+
+    /// Runs the message and snapshot operations every backend supports. Backends may keep
+    /// messages before the start offset around after a truncation, so only those from it on
+    /// are checked.
+    fn check_storage(mut storage: Box<dyn Storage>) {
+        assert!(storage.load_snapshot().unwrap().is_none());
+        storage.open_topic("t").unwrap();
+        assert_eq!((storage.start_offset("t"), storage.next_offset("t")), (0, 0));
+
+        let written = msgs(10);
+        assert_eq!(storage.append_msg("t", &written[0]).unwrap(), 0);
+        assert_eq!(storage.append_msgs("t", &written[1..]).unwrap(), 1);
+        assert_eq!(storage.next_offset("t"), 10);
+        let read = storage.read_msgs("t", 3, 4).unwrap();
+        assert_eq!(offsets(&read), [3, 4, 5, 6]);
+        assert_eq!((read[0].id.as_str(), read[0].payload.as_slice()), (written[3].id.as_str(), &b"m3"[..]));
+        assert_eq!(offsets(&storage.read_msgs("t", 8, 10).unwrap()), [8, 9]);
+
+        let by_count = Retention { max_msgs: 4, ..Retention::default() };
+        assert_eq!(storage.retention_start("t", &by_count, 2000).unwrap(), 6);
+        let by_age = Retention { max_age_ms: 30, ..Retention::default() };
+        assert_eq!(storage.retention_start("t", &by_age, 1085).unwrap(), 6);
+        let msg_bytes = written[0].to_proto().encoded_len() as u64;
+        let by_size = Retention { max_bytes: 3 * msg_bytes, ..Retention::default() };
+        assert_eq!(storage.retention_start("t", &by_size, 2000).unwrap(), 7);
+        assert_eq!(storage.retention_start("t", &Retention::default(), 2000).unwrap(), 0);
+
+        storage.truncate("t", 6).unwrap();
+        assert!(storage.start_offset("t") <= 6);
+        assert_eq!(offsets(&storage.read_msgs("t", 6, 10).unwrap()), [6, 7, 8, 9]);
+        assert_eq!(storage.append_msg("t", &msgs(1)[0]).unwrap(), 10);
+
+        let state = ProtoBroker { journal_seq: 7, ..ProtoBroker::default() };
+        storage.write_snapshot(&state).unwrap();
+        assert_eq!(storage.load_snapshot().unwrap().unwrap().journal_seq, 7);
+
+        storage.delete_topic("t").unwrap();
+        storage.open_topic("t").unwrap();
+        assert_eq!(storage.next_offset("t"), 0);
+        assert!(storage.read_msgs("t", 0, 10).unwrap().is_empty());
+    }
+
+    /// Checks that messages and journal entries written to the storage `open` returns are
+    /// there again after opening it anew.
+    fn check_reopen(open: impl Fn() -> Box<dyn Storage>) {
+        let mut storage = open();
+        storage.open_topic("t").unwrap();
+        storage.append_msgs("t", &msgs(3)).unwrap();
+        storage.append_journal(&ProtoJournalEntry { seq: 1, op: None }).unwrap();
+        drop(storage);
+
+        let mut storage = open();
+        storage.open_topic("t").unwrap();
+        assert_eq!(storage.next_offset("t"), 3);
+        assert_eq!(offsets(&storage.read_msgs("t", 0, 10).unwrap()), [0, 1, 2]);
+        let journal = storage.load_journal().unwrap();
+        assert_eq!(journal.iter().map(|entry| entry.seq).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn memory_storage() {
+        check_storage(Box::new(MemoryStorage::default()));
+    }
+
+    #[test]
+    fn file_storage() {
+        let dir = TempDir::new();
+        check_storage(Box::new(FileStorage::open(dir.path()).unwrap()));
+        let dir = TempDir::new();
+        check_reopen(|| Box::new(FileStorage::open(dir.path()).unwrap()));
+    }
+
+    #[test]
+    fn sqlite_storage() {
+        let dir = TempDir::new();
+        check_storage(Box::new(SqliteStorage::open(dir.path()).unwrap()));
+        let dir = TempDir::new();
+        check_reopen(|| Box::new(SqliteStorage::open(dir.path()).unwrap()));
+    }
+}
@@ -1,38 +1,616 @@
-use crate::broker_service::ProtoTopic;
+use crate::broker_service::{CreateTopicRequest, ProtoDeadLetterPolicy, ProtoRetention, ProtoTopic};
 
-use std::collections::HashSet;
-use crate::msg::Msg;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::Error;
+use crate::cursor::Cursor;
+use crate::dedup::DedupWindow;
+use crate::filter::Filter;
+use crate::msg::{DeadLettered, Expired, Msg};
+use crate::storage::Storage;
+use crate::utils::now_millis;
 
+/// How long the keys of posted messages are remembered when the topic sets no window.
+const DEFAULT_DEDUP_WINDOW_MS: u64 = 5 * 60 * 1000;
+
+/// Most messages a topic keeps in memory; the rest are read from storage when needed.
+pub const RESIDENT_WINDOW: usize = 10_000;
+
+/// Messages read from storage at a time when delivering past the resident window.
+const PAGE_SIZE: usize = 256;
+
+/// Limits on the messages a topic keeps, enforced periodically by the broker. A limit of 0
+/// means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retention {
+    pub max_age_ms: u64,
+    pub max_bytes: u64,
+    pub max_msgs: u64,
+}
+
+impl Retention {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age_ms == 0 && self.max_bytes == 0 && self.max_msgs == 0
+    }
+
+    pub fn from_proto(proto: ProtoRetention) -> Self {
+        Self {
+            max_age_ms: proto.max_age_ms,
+            max_bytes: proto.max_bytes,
+            max_msgs: proto.max_msgs,
+        }
+    }
+
+    pub fn to_proto(self) -> ProtoRetention {
+        ProtoRetention {
+            max_age_ms: self.max_age_ms,
+            max_bytes: self.max_bytes,
+            max_msgs: self.max_msgs,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
+pub struct DeadLetterPolicy {
+    pub topic: String,
+    pub max_attempts: u32,
+}
+
+impl DeadLetterPolicy {
+    pub fn from_proto(proto: ProtoDeadLetterPolicy) -> Self {
+        Self {
+            topic: proto.topic,
+            max_attempts: proto.max_attempts,
+        }
+    }
+
+    pub fn to_proto(&self) -> ProtoDeadLetterPolicy {
+        ProtoDeadLetterPolicy {
+            topic: self.topic.clone(),
+            max_attempts: self.max_attempts,
+        }
+    }
+}
+
+/// What `Topic::deliver` found for a subscriber.
+#[derive(Debug, Default)]
+pub struct Delivered {
+    /// Messages leased to the subscriber.
+    pub msgs: Vec<Msg>,
+    /// Copies, for the dead-letter topic, of the messages out of delivery attempts.
+    pub dead_lettered: Vec<Msg>,
+    /// Ids of the messages not matching the filter of the subscriber, to be acked for it.
+    pub filtered_out: Vec<String>,
+    /// Ids and offsets of the messages above read from storage past the resident window, for
+    /// the broker to index.
+    pub paged: Vec<(String, u64)>,
+}
+
+/// How many more messages, and payload bytes, a fetch may return.
+#[derive(Debug, Clone, Copy)]
+pub struct FetchBudget {
+    pub msgs: usize,
+    pub bytes: u64,
+    taken: bool,
+}
+
+impl FetchBudget {
+    /// A limit of 0 means unlimited.
+    pub fn new(max_msgs: u32, max_bytes: u64) -> Self {
+        Self {
+            msgs: if max_msgs == 0 { usize::MAX } else { max_msgs as usize },
+            bytes: if max_bytes == 0 { u64::MAX } else { max_bytes },
+            taken: false,
+        }
+    }
+
+    /// Takes a message of `size` payload bytes from the budget if it fits. The first message
+    /// always fits as long as any are left.
+    pub fn take(&mut self, size: u64) -> bool {
+        if self.msgs == 0 || (self.taken && size > self.bytes) {
+            return false;
+        }
+        self.msgs -= 1;
+        self.bytes = self.bytes.saturating_sub(size);
+        self.taken = true;
+        true
+    }
+}
+
+/// Settings chosen when a topic is created.
+#[derive(Debug, Clone, Default)]
+pub struct TopicConfig {
+    pub retention: Retention,
+    pub delete_on_ack: bool,
+    pub dead_letter: Option<DeadLetterPolicy>,
+    /// Topic expired messages not yet acked by every subscriber are moved to.
+    pub expiry_topic: Option<String>,
+    /// How long the keys of posted messages are remembered to recognise retried posts; 0 for
+    /// the default.
+    pub dedup_window_ms: u64,
+    /// Client the topic is the temporary reply topic of.
+    pub reply_owner: Option<String>,
+}
+
+impl TopicConfig {
+    pub fn from_request(req: &CreateTopicRequest) -> Self {
+        Self {
+            retention: Retention::from_proto(req.retention.clone().unwrap_or_default()),
+            delete_on_ack: req.delete_on_ack,
+            dead_letter: req.dead_letter.clone().map(DeadLetterPolicy::from_proto),
+            expiry_topic: Some(req.expiry_topic.clone()).filter(|topic| !topic.is_empty()),
+            dedup_window_ms: req.dedup_window_ms,
+            reply_owner: Some(req.reply_owner.clone()).filter(|owner| !owner.is_empty()),
+        }
+    }
+
+    pub fn to_request(&self, name: &str) -> CreateTopicRequest {
+        CreateTopicRequest {
+            name: name.to_string(),
+            retention: Some(self.retention.to_proto()),
+            delete_on_ack: self.delete_on_ack,
+            dead_letter: self.dead_letter.as_ref().map(DeadLetterPolicy::to_proto),
+            expiry_topic: self.expiry_topic.clone().unwrap_or_default(),
+            dedup_window_ms: self.dedup_window_ms,
+            reply_owner: self.reply_owner.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// A topic holds the messages still of interest to its subscribers in `msgs`; all of them
+/// are also kept by the broker's storage. Each subscriber has a cursor recording what it has
+/// acked. Messages acked by every subscriber are evicted from memory and remain readable from
+/// storage only; subscribers joining later start at `resident_from`. Topics created with
+/// `delete_on_ack` drop every fully acked message, not just the leading ones, and have it
+/// deleted from storage too.
+/// At most `RESIDENT_WINDOW` messages are held in memory, those from `resident_from` up to
+/// `resident_until`. Later ones stay in storage until eviction makes room for them, and are
+/// read from there meanwhile by `deliver`.
+#[derive(Debug)]
 pub struct Topic {
     pub name: String,
-    pub subscribers: HashSet<String>,
-    pub msgs: Vec<Msg>,
+    pub subscribers: HashMap<String, Cursor>,
+    pub msgs: VecDeque<Msg>,
+    pub config: TopicConfig,
+    /// Offset of the oldest message not yet dropped by retention.
+    pub start_offset: u64,
+    pub next_offset: u64,
+    pub resident_from: u64,
+    /// Offset past the last message loaded into `msgs`.
+    pub resident_until: u64,
+    /// Ids of the messages past `resident_until` delivered from storage, by offset, until
+    /// every subscriber acks them or they are loaded.
+    pub paged: BTreeMap<u64, String>,
+    /// Offsets from `resident_from` on whose messages were deleted out of order, so that they
+    /// are not reloaded from storage.
+    pub deleted: BTreeSet<u64>,
+    pub dedup: DedupWindow,
+    /// Milliseconds since the Unix epoch at which the topic was last posted to, or fetched
+    /// from by its reply owner. Not persisted.
+    pub last_active: u64,
 }
 
 impl Topic {
-    pub fn new(name: &str) -> Self {
+    pub fn new(name: &str, config: TopicConfig) -> Self {
         Self {
             name: name.to_string(),
-            subscribers: HashSet::new(),
-            msgs: Vec::new(),
+            subscribers: HashMap::new(),
+            msgs: VecDeque::new(),
+            config,
+            start_offset: 0,
+            next_offset: 0,
+            resident_from: 0,
+            resident_until: 0,
+            paged: BTreeMap::new(),
+            deleted: BTreeSet::new(),
+            dedup: DedupWindow::default(),
+            last_active: now_millis(),
         }
     }
 
+    /// Subscribers of snapshots taken before cursors existed start at `resident_from`; their
+    /// acks are restored by the broker.
     pub fn from_proto(proto: ProtoTopic) -> Self {
+        let mut msgs: VecDeque<Msg> = proto.msgs.into_iter().map(Msg::from_proto).collect();
+        for (i, msg) in msgs.iter_mut().enumerate() {
+            msg.offset = proto.resident_from + i as u64;
+        }
+        let deleted: BTreeSet<u64> = proto.deleted_offsets.into_iter().collect();
+        let mut subscribers: HashMap<String, Cursor> = proto
+            .cursors
+            .into_iter()
+            .map(|(client_id, cursor)| (client_id, Cursor::from_proto(cursor)))
+            .collect();
+        for client_id in proto.subscribers {
+            let cursor = subscribers
+                .entry(client_id)
+                .or_insert_with(|| Cursor::new(proto.resident_from, deleted.clone()));
+            cursor.direct = true;
+        }
         Self {
             name: proto.name,
-            subscribers: proto.subscribers.into_iter().collect(),
-            msgs: proto.msgs.into_iter().map(Msg::from_proto).collect(),
+            subscribers,
+            config: TopicConfig {
+                retention: Retention::from_proto(proto.retention.unwrap_or_default()),
+                delete_on_ack: proto.delete_on_ack,
+                dead_letter: proto.dead_letter.map(DeadLetterPolicy::from_proto),
+                expiry_topic: Some(proto.expiry_topic).filter(|topic| !topic.is_empty()),
+                dedup_window_ms: proto.dedup_window_ms,
+                reply_owner: Some(proto.reply_owner).filter(|owner| !owner.is_empty()),
+            },
+            start_offset: proto.start_offset,
+            next_offset: proto.resident_from + msgs.len() as u64,
+            resident_from: proto.resident_from,
+            resident_until: proto.resident_from + msgs.len() as u64,
+            paged: BTreeMap::new(),
+            deleted,
+            dedup: DedupWindow::from_proto(proto.dedup),
+            last_active: now_millis(),
+            msgs,
         }
     }
 
+    /// Messages are not included, they are restored from storage.
     pub fn to_proto(&self) -> ProtoTopic {
         ProtoTopic {
             name: self.name.clone(),
-            subscribers: self.subscribers.iter().cloned().collect(),
-            msgs: self.msgs.iter().map(Msg::to_proto).collect(),
+            subscribers: vec![],
+            msgs: vec![],
+            resident_from: self.resident_from,
+            retention: Some(self.config.retention.to_proto()),
+            start_offset: self.start_offset,
+            delete_on_ack: self.config.delete_on_ack,
+            dead_letter: self.config.dead_letter.as_ref().map(DeadLetterPolicy::to_proto),
+            expiry_topic: self.config.expiry_topic.clone().unwrap_or_default(),
+            dedup_window_ms: self.config.dedup_window_ms,
+            dedup: self.dedup.to_proto(),
+            reply_owner: self.config.reply_owner.clone().unwrap_or_default(),
+            deleted_offsets: self.deleted.iter().copied().collect(),
+            cursors: self
+                .subscribers
+                .iter()
+                .map(|(client_id, cursor)| (client_id.clone(), cursor.to_proto()))
+                .collect(),
+        }
+    }
+
+    pub fn dedup_window_ms(&self) -> u64 {
+        match self.config.dedup_window_ms {
+            0 => DEFAULT_DEDUP_WINDOW_MS,
+            window_ms => window_ms,
+        }
+    }
+
+    /// Offset of the message `msg` repeats the post of, if it was posted within the dedup
+    /// window before `now`.
+    pub fn posted_offset(&self, msg: &Msg, now: u64) -> Option<u64> {
+        let key = msg.dedup_key()?;
+        self.dedup.get(&key, now.saturating_sub(self.dedup_window_ms()))
+    }
+
+    /// Remembers the key of `msg`, if any, and forgets those that fell out of the window.
+    pub fn remember(&mut self, msg: &Msg, now: u64) {
+        self.dedup.expire(now.saturating_sub(self.dedup_window_ms()));
+        if let Some(key) = msg.dedup_key() {
+            self.dedup.insert(key, msg.offset, msg.timestamp);
+        }
+    }
+
+    /// Adds `msg`, just stored, keeping it in memory if the resident window reaches up to it
+    /// and has room. Returns whether it does.
+    pub fn append(&mut self, msg: Msg) -> bool {
+        self.next_offset = msg.offset + 1;
+        if self.resident_until != msg.offset || self.msgs.len() >= RESIDENT_WINDOW {
+            return false;
+        }
+        self.resident_until = self.next_offset;
+        self.msgs.push_back(msg);
+        true
+    }
+
+    /// Loads the messages following the resident window from `storage` once eviction has
+    /// emptied half of it, until it is full again. Returns the ids and offsets of the messages
+    /// loaded.
+    pub fn refill(&mut self, storage: &dyn Storage) -> Result<Vec<(String, u64)>, Error> {
+        let mut loaded = vec![];
+        if self.msgs.len() > RESIDENT_WINDOW / 2 {
+            return Ok(loaded);
+        }
+        while self.resident_until < self.next_offset && self.msgs.len() < RESIDENT_WINDOW {
+            let page = storage.read_msgs(&self.name, self.resident_until, RESIDENT_WINDOW - self.msgs.len())?;
+            let Some(last) = page.last() else {
+                self.resident_until = self.next_offset;
+                break;
+            };
+            self.resident_until = last.offset + 1;
+            for msg in page.into_iter().filter(|msg| !self.deleted.contains(&msg.offset)) {
+                self.paged.remove(&msg.offset);
+                loaded.push((msg.id.clone(), msg.offset));
+                self.msgs.push_back(msg);
+            }
+        }
+        self.update_resident_from();
+        Ok(loaded)
+    }
+
+    /// A new subscriber starts at the oldest resident message. An existing one keeps its
+    /// position and gets `filter` instead of its current one. `direct` tells whether the
+    /// client subscribes by the topic's name rather than through a wildcard pattern.
+    pub fn subscribe(&mut self, client_id: &str, filter: Option<Filter>, direct: bool) {
+        let cursor = self
+            .subscribers
+            .entry(client_id.to_string())
+            .or_insert_with(|| Cursor::new(self.resident_from, self.deleted.clone()));
+        cursor.filter = filter;
+        cursor.direct |= direct;
+    }
+
+    pub fn ack(&mut self, client_id: &str, offset: u64) {
+        if let Some(cursor) = self.subscribers.get_mut(client_id) {
+            cursor.ack(offset);
+        }
+    }
+
+    /// Leases the due, unexpired messages `client_id` has neither acked nor holds a lease on
+    /// until `lease_until`, and returns them. Messages before its cursor are skipped straight
+    /// away; those past the resident window are read from `storage`, up to a window's worth.
+    /// Messages that have used up their delivery attempts are not delivered; copies for the
+    /// dead-letter topic are returned for them instead. Delivery stops at the first message
+    /// that does not fit into `budget`.
+    /// A message with an ordering key is held back while an earlier message with that key is
+    /// still unacked, whether it is leased, nacked or not yet due.
+    /// Messages not matching the filter of `client_id` are not delivered either, but returned
+    /// for the broker to ack.
+    pub fn deliver(
+        &mut self,
+        client_id: &str,
+        now: u64,
+        lease_until: u64,
+        budget: &mut FetchBudget,
+        storage: &dyn Storage,
+    ) -> Result<Delivered, Error> {
+        let mut delivered = Delivered::default();
+        let Some(cursor) = self.subscribers.get_mut(client_id) else {
+            return Ok(delivered);
+        };
+        let max_attempts = self.config.dead_letter.as_ref().map_or(u32::MAX, |policy| policy.max_attempts);
+        let first = self.msgs.partition_point(|msg| msg.offset < cursor.next);
+        let mut from = self.resident_until.max(cursor.next);
+        let mut pending_keys = HashSet::new();
+        let name = &self.name;
+        // Offers `msg` to the subscriber, returning whether it was handed out in any way, or
+        // `None` once the budget is used up.
+        let mut offer = |msg: &Msg| -> Option<bool> {
+            if msg.is_expired(now) || cursor.acked.contains(&msg.offset) {
+                return Some(false);
+            }
+            if cursor.filter.as_ref().is_some_and(|filter| !filter.matches(msg)) {
+                delivered.filtered_out.push(msg.id.clone());
+                return Some(true);
+            }
+            let key = &msg.ordering_key;
+            if !key.is_empty() && !pending_keys.insert(key.clone()) {
+                return Some(false);
+            }
+            if msg.deliver_at > now || cursor.is_leased(msg.offset, now) {
+                return Some(false);
+            }
+            if cursor.attempts(msg.offset) >= max_attempts {
+                pending_keys.remove(key);
+                let delivery = cursor.deliveries.get(&msg.offset).cloned().unwrap_or_default();
+                let mut copy = msg.forwarded_copy();
+                copy.dead_lettered = Some(DeadLettered {
+                    topic: name.clone(),
+                    offset: msg.offset,
+                    msg_id: msg.id.clone(),
+                    client_id: client_id.to_string(),
+                    attempts: delivery.attempts,
+                    reason: delivery.reason,
+                });
+                delivered.dead_lettered.push(copy);
+                return Some(true);
+            }
+            if !budget.take(msg.payload.len() as u64) {
+                return None;
+            }
+            let mut msg = msg.clone();
+            msg.attempts = cursor.deliver(msg.offset, lease_until);
+            delivered.msgs.push(msg);
+            Some(true)
+        };
+
+        for msg in self.msgs.range(first..) {
+            if offer(msg).is_none() {
+                return Ok(delivered);
+            }
+        }
+        let mut read = 0;
+        while from < self.next_offset && read < RESIDENT_WINDOW {
+            let page = storage.read_msgs(&self.name, from, PAGE_SIZE)?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            from = last.offset + 1;
+            read += page.len();
+            for msg in page.iter().filter(|msg| !self.deleted.contains(&msg.offset)) {
+                match offer(msg) {
+                    None => return Ok(delivered),
+                    Some(true) => {
+                        self.paged.insert(msg.offset, msg.id.clone());
+                        delivered.paged.push((msg.id.clone(), msg.offset));
+                    }
+                    Some(false) => {}
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Moves the expiry of a lease `client_id` still holds on `offset` to `lease_until`.
+    /// Returns whether there was such a lease.
+    pub fn extend_lease(&mut self, client_id: &str, offset: u64, now: u64, lease_until: u64) -> bool {
+        match self.subscribers.get_mut(client_id).and_then(|cursor| cursor.deliveries.get_mut(&offset)) {
+            Some(delivery) if delivery.until > now => {
+                delivery.until = lease_until;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Makes `offset` deliverable to `client_id` again from `redeliver_at` on. Returns false
+    /// if the client does not subscribe or has already acked it.
+    pub fn nack(&mut self, client_id: &str, offset: u64, redeliver_at: u64, reason: &str) -> bool {
+        match self.subscribers.get_mut(client_id) {
+            Some(cursor) if !cursor.is_acked(offset) => {
+                let delivery = cursor.deliveries.entry(offset).or_default();
+                delivery.until = redeliver_at;
+                delivery.reason = reason.to_string();
+                delivery.nacked = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the oldest message with `correlation_id` that `client_id` has not acked.
+    pub fn find_reply(&self, client_id: &str, correlation_id: &str) -> Option<&Msg> {
+        let cursor = self.subscribers.get(client_id)?;
+        self.msgs
+            .iter()
+            .find(|msg| msg.correlation_id == correlation_id && !cursor.is_acked(msg.offset))
+    }
+
+    pub fn is_fully_acked(&self, offset: u64) -> bool {
+        !self.subscribers.is_empty() && self.subscribers.values().all(|cursor| cursor.is_acked(offset))
+    }
+
+    /// Returns the offsets of the resident messages expired by `now`, along with copies for
+    /// the expiry topic of those some subscriber has yet to ack, if the topic has one.
+    /// Messages past the resident window are left until they are loaded; `deliver` skips them
+    /// meanwhile.
+    pub fn expired(&self, now: u64) -> (Vec<u64>, Vec<Msg>) {
+        let mut offsets = vec![];
+        let mut copies = vec![];
+        for msg in self.msgs.iter().filter(|msg| msg.is_expired(now)) {
+            offsets.push(msg.offset);
+            if self.config.expiry_topic.is_some() && !self.is_fully_acked(msg.offset) {
+                let mut copy = msg.forwarded_copy();
+                copy.expired = Some(Expired {
+                    topic: self.name.clone(),
+                    offset: msg.offset,
+                    msg_id: msg.id.clone(),
+                    expires_at: msg.expires_at,
+                });
+                copies.push(copy);
+            }
+        }
+        (offsets, copies)
+    }
+
+    /// Drops the messages at `offsets` from memory as if every subscriber had acked them,
+    /// returning their ids. They are not reloaded from storage either.
+    pub fn expire(&mut self, offsets: &[u64]) -> Vec<String> {
+        let mut expired = vec![];
+        for &offset in offsets {
+            if let Ok(i) = self.msgs.binary_search_by_key(&offset, |msg| msg.offset) {
+                expired.extend(self.msgs.remove(i).map(|msg| msg.id));
+                self.deleted.insert(offset);
+                for cursor in self.subscribers.values_mut() {
+                    cursor.ack(offset);
+                }
+            }
+        }
+        self.update_resident_from();
+        expired.extend(self.evict_leading());
+        expired
+    }
+
+    /// Drops the messages every current subscriber has acked from memory, returning their
+    /// ids. Only leading messages are dropped unless the topic is `delete_on_ack`. A topic
+    /// without subscribers keeps its messages.
+    pub fn evict_acked(&mut self) -> Vec<String> {
+        let mut evicted = vec![];
+        if self.config.delete_on_ack && !self.subscribers.is_empty() {
+            let (subscribers, deleted) = (&self.subscribers, &mut self.deleted);
+            self.msgs.retain(|msg| {
+                let acked = subscribers.values().all(|cursor| cursor.is_acked(msg.offset));
+                if acked {
+                    deleted.insert(msg.offset);
+                    evicted.push(msg.id.clone());
+                }
+                !acked
+            });
+        }
+        let settled: Vec<u64> = self.paged.keys().copied().filter(|&offset| self.is_fully_acked(offset)).collect();
+        for offset in settled {
+            evicted.extend(self.settle_paged(offset));
+        }
+        evicted.extend(self.evict_leading());
+        evicted
+    }
+
+    /// Like `evict_acked` after an ack of `offset`, only looking at that message and the
+    /// leading ones.
+    pub fn evict_acked_at(&mut self, offset: u64) -> Vec<String> {
+        let mut evicted = vec![];
+        if self.is_fully_acked(offset) {
+            if offset >= self.resident_until {
+                evicted.extend(self.settle_paged(offset));
+            } else if self.config.delete_on_ack {
+                if let Ok(i) = self.msgs.binary_search_by_key(&offset, |msg| msg.offset) {
+                    evicted.extend(self.msgs.remove(i).map(|msg| msg.id));
+                    self.deleted.insert(offset);
+                }
+            }
+        }
+        evicted.extend(self.evict_leading());
+        evicted
+    }
+
+    /// Forgets the message at `offset`, past the resident window and acked by every
+    /// subscriber, returning its id if it was delivered from storage.
+    fn settle_paged(&mut self, offset: u64) -> Option<String> {
+        if self.config.delete_on_ack {
+            self.deleted.insert(offset);
+        }
+        self.paged.remove(&offset)
+    }
+
+    fn evict_leading(&mut self) -> Vec<String> {
+        let Some(acked_until) = self.subscribers.values().map(|cursor| cursor.next).min() else {
+            return vec![];
+        };
+        let evicted = self.msgs.partition_point(|msg| msg.offset < acked_until);
+        let mut evicted: Vec<String> = self.msgs.drain(..evicted).map(|msg| msg.id).collect();
+        if self.msgs.is_empty() && acked_until > self.resident_until {
+            // Messages past the window acked by every subscriber need not be loaded at all.
+            self.resident_until = acked_until.min(self.next_offset);
+            let pending = self.paged.split_off(&self.resident_until);
+            evicted.extend(std::mem::replace(&mut self.paged, pending).into_values());
         }
+        self.update_resident_from();
+        evicted
     }
-}
\ No newline at end of file
+
+    /// Drops the messages before `start` from memory, returning their ids.
+    pub fn truncate(&mut self, start: u64) -> Vec<String> {
+        let removed = self.msgs.partition_point(|msg| msg.offset < start);
+        let mut removed: Vec<String> = self.msgs.drain(..removed).map(|msg| msg.id).collect();
+        if self.msgs.is_empty() && start > self.resident_until {
+            self.resident_until = start.min(self.next_offset);
+        }
+        let kept = self.paged.split_off(&start);
+        removed.extend(std::mem::replace(&mut self.paged, kept).into_values());
+        for cursor in self.subscribers.values_mut() {
+            cursor.advance_to(start);
+        }
+        self.start_offset = self.start_offset.max(start);
+        self.update_resident_from();
+        removed
+    }
+
+    fn update_resident_from(&mut self) {
+        self.resident_from = self.msgs.front().map_or(self.resident_until, |msg| msg.offset);
+        self.deleted = self.deleted.split_off(&self.resident_from);
+    }
+}
@@ -0,0 +1,23 @@
+use crate::msg::Msg;
+
+/// Posts and acks collected by a client until it commits or aborts them. Open transactions are
+/// not persisted: a restarted broker has aborted them all.
+#[derive(Debug)]
+pub struct Transaction {
+    /// Milliseconds since the Unix epoch at which the transaction was begun.
+    pub started: u64,
+    /// Messages to post, with the topics they go to.
+    pub posts: Vec<(String, Msg)>,
+    /// Messages to ack, as message and client ids.
+    pub acks: Vec<(String, String)>,
+}
+
+impl Transaction {
+    pub fn new(started: u64) -> Self {
+        Self {
+            started,
+            posts: vec![],
+            acks: vec![],
+        }
+    }
+}
@@ -0,0 +1,98 @@
+/// Most `#` levels a subscription pattern may have.
+pub const MAX_MULTI_LEVEL_WILDCARDS: usize = 4;
+
+/// Whether `name` is a subscription pattern rather than a topic name, having a `*` or `#`
+/// level.
+pub fn is_pattern(name: &str) -> bool {
+    levels(name).any(|level| level == "*" || level == "#")
+}
+
+/// Number of `#` levels of `pattern`, consecutive ones counting as one as they match the same.
+pub fn multi_level_wildcards(pattern: &str) -> usize {
+    collapsed_levels(pattern).iter().filter(|&&level| level == "#").count()
+}
+
+/// Whether topic `name` matches subscription `pattern`. Both are compared level by level,
+/// levels being separated by `.` or `/`: a `*` level matches any single level, and a `#` level
+/// any number of levels, including none.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let name: Vec<&str> = levels(name).collect();
+    // matched[i] tells whether the pattern levels seen so far match the first i name levels,
+    // so that matching takes time proportional to the product of both lengths.
+    let mut matched = vec![false; name.len() + 1];
+    matched[0] = true;
+    for level in collapsed_levels(pattern) {
+        if level == "#" {
+            for i in 1..=name.len() {
+                matched[i] = matched[i] || matched[i - 1];
+            }
+        } else {
+            for i in (1..=name.len()).rev() {
+                matched[i] = matched[i - 1] && (level == "*" || level == name[i - 1]);
+            }
+            matched[0] = false;
+        }
+    }
+    matched[name.len()]
+}
+
+/// The levels of `pattern` with runs of `#` levels reduced to one.
+fn collapsed_levels(pattern: &str) -> Vec<&str> {
+    let mut levels: Vec<&str> = levels(pattern).collect();
+    levels.dedup_by(|level, previous| *level == "#" && *previous == "#");
+    levels
+}
+
+fn levels(name: &str) -> impl Iterator<Item = &str> {
+    name.split(['.', '/'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_patterns() {
+        assert!(is_pattern("orders.*"));
+        assert!(is_pattern("#"));
+        assert!(is_pattern("orders/#/created"));
+        assert!(!is_pattern("orders.eu"));
+        assert!(!is_pattern("orders*.eu"));
+        assert!(!is_pattern("orders.e#"));
+    }
+
+    #[test]
+    fn matches_single_levels() {
+        assert!(matches("orders.*.created", "orders.eu.created"));
+        assert!(matches("orders/*/created", "orders.eu/created"));
+        assert!(!matches("orders.*.created", "orders.created"));
+        assert!(!matches("orders.*.created", "orders.eu.west.created"));
+        assert!(!matches("orders.*", "orders"));
+        assert!(matches("orders.eu", "orders.eu"));
+        assert!(!matches("orders.eu", "orders.us"));
+    }
+
+    #[test]
+    fn matches_any_number_of_levels() {
+        assert!(matches("orders.#", "orders"));
+        assert!(matches("orders.#", "orders.eu"));
+        assert!(matches("orders.#", "orders/eu/west"));
+        assert!(matches("orders.#.created", "orders.created"));
+        assert!(matches("orders.#.created", "orders.eu.west.created"));
+        assert!(matches("#", "orders.eu"));
+        assert!(matches("#.*", "orders"));
+        assert!(!matches("orders.#", "order.eu"));
+        assert!(!matches("orders.#.created", "orders.eu.updated"));
+        assert!(matches("orders.#.#", "orders"));
+    }
+
+    #[test]
+    fn many_multi_level_wildcards_match_quickly() {
+        let name = vec!["a"; 64].join(".");
+        let pattern = format!("{}z", "#.".repeat(64));
+        assert!(!matches(&pattern, &name));
+        assert!(matches(&format!("{}a", "#.*.".repeat(32)), &name));
+        assert_eq!(multi_level_wildcards(&pattern), 1);
+        assert_eq!(multi_level_wildcards("#.a.#.*.#"), 3);
+    }
+}